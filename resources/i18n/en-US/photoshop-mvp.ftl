@@ -0,0 +1,272 @@
+# English (base) strings for the Photoshop MVP UI.
+# To add a language, copy this file to resources/i18n/<locale>/photoshop-mvp.ftl and translate the values.
+
+app-title = Photoshop MVP
+
+navigator-title = Navigator
+
+zoom-label = Zoom (%)
+zoom-placeholder = e.g. 250
+zoom-in = +
+zoom-out = −
+zoom-fit = Fit to View
+
+tools-title = Tools
+edit-undo = Undo
+edit-redo = Redo
+
+tool-brush = Brush
+tool-eraser = Eraser
+
+erase-mode-transparent = Erase to transparent
+erase-mode-background = Erase to background color
+erase-mode-secondary = Erase to secondary color
+
+tool-polygon = Polygon
+tool-select = Select
+tool-line = Line
+tool-rectangle = Rectangle
+tool-ellipse = Ellipse
+shape-fill = Filled shapes
+tool-fill = Fill
+
+fill-tolerance = Fill Tolerance
+fill-tolerance-placeholder = 0-510
+fill-feather = Fill Feather (px)
+fill-feather-placeholder = 0 = hard edge
+tool-crop = Crop
+tool-spray = Spray
+tool-pencil = Pencil
+tool-gradient = Gradient
+gradient-linear = Linear
+gradient-radial = Radial
+gradient-end-placeholder = End color (#RRGGBB)
+spray-density = Spray density: { $count } px
+tool-smudge = Smudge
+tool-picker = Eyedropper
+picker-sample-size = Sample Size
+picker-sample-1 = Point
+picker-sample-3 = 3×3
+picker-sample-5 = 5×5
+picker-switch-back = Return to previous tool after picking
+picker-merged = Sample all layers
+
+brush-shape-title = Brush Shape
+brush-shape-square = Square
+brush-shape-circle = Circle
+brush-load-stamp = Load Stamp
+brush-rotate-to-direction = Rotate to stroke direction
+brush-dynamic-size = Thinner when moving fast
+
+stamp-scaling-title = Stamp Scaling
+stamp-scaling-nearest = Nearest
+stamp-scaling-bilinear = Bilinear
+
+falloff-title = Falloff Curve
+falloff-linear = Linear
+falloff-ease-in = Ease In
+falloff-ease-out = Ease Out
+symmetry-title = Symmetry
+symmetry-none = Off
+symmetry-vertical = Mirror left/right
+symmetry-horizontal = Mirror top/bottom
+symmetry-both = Mirror both
+
+brush-size-title = Brush Size
+brush-size-placeholder = Enter size (px)
+brush-antialiasing = Anti-aliasing
+undo-split-strokes = Split long strokes for undo
+brush-opacity = Opacity: { $percent }%
+brush-flow = Flow: { $percent }%
+brush-hardness = Hardness: { $percent }%
+brush-step = Min stamp step: { $step } px
+brush-preview-title = Brush Preview
+brush-color-title = Brush Color
+
+color-r-placeholder = R (0-255)
+color-g-placeholder = G (0-255)
+color-b-placeholder = B (0-255)
+color-a-placeholder = A (0-255)
+color-hex-placeholder = Hex (#RRGGBB)
+
+brush-color-set-default = Set as Default
+brush-color-swap = Swap Primary/Secondary
+
+palette-title = Color Palette
+palette-load = Load .gpl
+palette-save-recent = Save Recent as .gpl
+recent-colors-title = Recent Colors
+custom-palette-title = Custom Palette
+custom-palette-add = Add Current Color
+remap-title = Palette Remap
+remap-placeholder = r,g,b=r,g,b; ...
+remap-apply = Apply Remap
+pack-title = Channel Packing
+pack-red = Red from
+pack-green = Green from
+pack-blue = Blue from
+pack-alpha = Alpha from
+pack-apply = Pack Channels
+filters-title = Filters
+filter-preview = Preview
+filter-grayscale = Grayscale
+filter-sepia = Sepia
+filter-invert = Invert
+filter-blur-radius = Blur radius: { $radius }
+filter-blur = Blur
+filter-gaussian-blur = Gaussian Blur
+filter-sharpen = Sharpen
+filter-edge-detect = Edge Detect
+filter-brightness = Brightness: { $value }
+filter-contrast = Contrast: { $value }×
+filter-brightness-contrast = Apply Brightness/Contrast
+filter-hue = Hue: { $value }°
+filter-saturation = Saturation: { $value }%
+filter-lightness = Lightness: { $value }%
+filter-hsl = Apply Hue/Saturation
+filter-threshold-level = Threshold: { $level }
+filter-threshold = Threshold
+filter-posterize-levels = Posterize levels: { $levels }
+filter-posterize = Posterize
+selection-title = Selection
+selection-from-alpha = Selection from Alpha
+selection-to-alpha = Alpha from Selection
+selection-clear = Deselect
+selection-export = Export Selection
+
+layers-title = Layers
+layers-active = { $name } ({ $index }/{ $count })
+layers-previous = Below
+layers-next = Above
+layers-add = Add Layer
+layers-delete = Delete Layer
+layers-move-up = Move Up
+layers-move-down = Move Down
+layers-visible = Visible
+layers-opacity = Layer opacity: { $percent }%
+blend-normal = Normal
+blend-multiply = Multiply
+blend-screen = Screen
+blend-overlay = Overlay
+blend-add = Add
+
+background-title = Background Color
+background-white = White
+background-gray = Gray
+background-transparent = Transparent
+
+new-document-title = New Document
+new-background-white = White
+new-background-transparent = Transparent
+new-background-custom = Secondary color
+new-width-placeholder = Width
+new-height-placeholder = Height
+new-document = New
+resize-title = Resize Image
+resize-lock-aspect = Lock aspect ratio
+resize-nearest = Nearest
+resize-triangle = Bilinear
+resize-lanczos = Lanczos
+resize-apply = Resize
+transform-flip-horizontal = Flip Horizontal
+transform-flip-vertical = Flip Vertical
+transform-rotate-90 = Rotate 90°
+transform-rotate-180 = 180°
+transform-rotate-270 = 270°
+transform-rotate-placeholder = Degrees
+transform-rotate = Rotate
+file-open-image = Open Image
+file-url-placeholder = https://...
+file-open-url = Open from URL
+file-save-image = Save Image
+file-jpeg-quality = JPEG quality: { $quality }
+file-export-premultiplied = Save premultiplied alpha
+file-export-view = Export View
+file-export-channel = Export Channel
+tile-width-placeholder = Tile W
+tile-height-placeholder = Tile H
+tile-margin-placeholder = Margin
+tile-spacing-placeholder = Spacing
+file-export-tiles = Export Tiles
+channel-red = R
+channel-green = G
+channel-blue = B
+channel-alpha = A
+channel-zero = 0
+channel-full = 1
+
+clear-confirm-question = Clear everything?
+clear-confirm-yes = Yes
+clear-confirm-cancel = Cancel
+clear-canvas = Clear Canvas
+
+settings-reset = Reset to Defaults
+exit = EXIT
+exit-confirm-question = You have unsaved changes. Save before exiting?
+exit-confirm-save = Save
+exit-confirm-discard = Exit Without Saving
+exit-confirm-cancel = Cancel
+status-cursor = X { $x }  Y { $y }  RGBA({ $rgba })
+status-cursor-none = X -  Y -  RGBA(-)
+
+# Status bar messages
+status-picked = Picked { $name }
+status-picked-transparent = Picked area is fully transparent
+status-nothing-selected = Nothing is selected
+status-nothing-to-undo = Nothing to undo
+status-nothing-to-redo = Nothing to redo
+status-only-layer = Cannot delete the only layer
+status-stamp-loaded = Stamp loaded
+status-stamp-damaged = Stamp is damaged, loaded what could be decoded ({ $error })
+status-stamp-load-failed = Could not load stamp: { $error }
+status-image-opening = Opening { $path }...
+status-image-opened = Opened { $path }
+status-image-damaged = Image is damaged, loaded what could be decoded ({ $error })
+status-image-open-failed = Could not open image, canvas left unchanged: { $error }
+status-image-saved = Saved image to { $path }
+status-image-save-failed = Could not save image: { $error }
+status-url-downloading = Downloading { $url }...
+status-url-opened = Opened image from URL
+status-url-open-failed = Could not open URL: { $error }
+status-selection-exported = Exported selection to { $path }
+status-selection-export-failed = Could not export selection: { $error }
+status-view-exported = Exported view to { $path }
+status-view-export-failed = Could not export view: { $error }
+status-channel-exported = Exported channel to { $path }
+status-channel-export-failed = Could not export channel: { $error }
+status-tiles-exported = Exported { $count } tiles next to { $path }
+status-tiles-none = Image is smaller than one tile, nothing exported
+status-tiles-export-failed = Could not export tiles: { $error }
+status-tile-width-invalid = Tile width must be a whole number of pixels
+status-tile-height-invalid = Tile height must be a whole number of pixels
+status-tile-margin-invalid = Tile margin must be a whole number of pixels
+status-tile-spacing-invalid = Tile spacing must be a whole number of pixels
+status-tile-size-zero = Tile width and height must be greater than zero
+status-palette-loaded = Loaded { $count } colors
+status-palette-loaded-partial = Loaded { $count } colors, { $skipped } did not fit
+status-palette-empty = Palette file contains no colors
+status-palette-load-failed = Could not load palette: { $error }
+status-palette-saved = Saved palette to { $path }
+status-palette-save-failed = Could not save palette: { $error }
+status-no-recent-colors = No recent colors to save
+status-custom-palette-full = Custom palette is full
+status-remap-invalid = Remap must be pairs like 255,0,0=0,0,255; ...
+status-remapped = Remapped { $count } colors
+status-default-color-saved = Default brush color saved
+status-settings-save-failed = Could not save settings: { $error }
+status-settings-reset = Settings reset to defaults
+status-settings-reset-save-failed = Settings reset, but could not save them: { $error }
+status-zoom-invalid = Zoom must be a percentage like 250
+status-brush-size-invalid = Brush size must be between 1 and { $max } pixels
+status-document-new = New { $width }x{ $height } document
+status-document-size-invalid = Width and height must be between 1 and { $max } pixels
+status-resized = Resized image to { $width }x{ $height }
+status-resize-width-invalid = Width must be between 1 and { $max } pixels
+status-resize-height-invalid = Height must be between 1 and { $max } pixels
+status-rotated = Rotated image by { $degrees }°
+status-rotate-invalid = Rotation angle must be a number of degrees
+status-cropped = Cropped image to { $width }x{ $height }
+status-crop-empty = Crop area is empty
+status-clipboard-pasted = Pasted image from clipboard
+status-clipboard-no-image = Clipboard does not contain an image
+status-clipboard-paste-failed = Could not paste image: { $error }
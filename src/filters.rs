@@ -0,0 +1,641 @@
+use image::{Rgba, RgbaImage};
+use std::sync::Arc;
+
+pub trait Filter {
+    fn apply(&self, image: &mut RgbaImage);
+    fn name(&self) -> &'static str;
+}
+
+#[derive(Default, Clone, PartialEq)]
+pub struct FilterParams {
+    pub blur_radius: u32,
+    pub brightness: i32,
+    pub contrast: f32,
+    pub threshold: u8,
+    pub posterize_levels: u32,
+    pub hue: f32,
+    pub saturation: f32,
+    pub lightness: f32,
+}
+
+pub fn registry(params: &FilterParams) -> Vec<Arc<dyn Filter>> {
+    vec![
+        Arc::new(Grayscale),
+        Arc::new(Sepia),
+        Arc::new(Invert),
+        Arc::new(Blur {
+            radius: params.blur_radius,
+        }),
+        Arc::new(GaussianBlur {
+            radius: params.blur_radius as f32,
+        }),
+        Arc::new(Sharpen),
+        Arc::new(EdgeDetect),
+        Arc::new(BrightnessContrast {
+            brightness: params.brightness,
+            contrast: params.contrast,
+        }),
+        Arc::new(HueSaturation {
+            hue: params.hue,
+            saturation: params.saturation,
+            lightness: params.lightness,
+        }),
+        Arc::new(Threshold {
+            level: params.threshold,
+        }),
+        Arc::new(Posterize {
+            levels: params.posterize_levels,
+        }),
+    ]
+}
+
+struct Grayscale;
+
+impl Filter for Grayscale {
+    fn apply(&self, image: &mut RgbaImage) {
+        for pixel in image.pixels_mut() {
+            let luma = (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32).round() as u8;
+            *pixel = Rgba([luma, luma, luma, pixel[3]]);
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "filter-grayscale"
+    }
+}
+
+struct Sepia;
+
+impl Filter for Sepia {
+    fn apply(&self, image: &mut RgbaImage) {
+        apply_sepia(image);
+    }
+
+    fn name(&self) -> &'static str {
+        "filter-sepia"
+    }
+}
+
+pub fn apply_sepia(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+        let tone = |red: f32, green: f32, blue: f32| (red * r + green * g + blue * b).round().min(255.0) as u8;
+        *pixel = Rgba([
+            tone(0.393, 0.769, 0.189),
+            tone(0.349, 0.686, 0.168),
+            tone(0.272, 0.534, 0.131),
+            pixel[3],
+        ]);
+    }
+}
+
+struct Invert;
+
+impl Filter for Invert {
+    fn apply(&self, image: &mut RgbaImage) {
+        for pixel in image.pixels_mut() {
+            *pixel = Rgba([255 - pixel[0], 255 - pixel[1], 255 - pixel[2], pixel[3]]);
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "filter-invert"
+    }
+}
+
+struct Blur {
+    radius: u32,
+}
+
+impl Filter for Blur {
+    fn apply(&self, image: &mut RgbaImage) {
+        blur_image(image, self.radius);
+    }
+
+    fn name(&self) -> &'static str {
+        "filter-blur"
+    }
+}
+
+struct GaussianBlur {
+    radius: f32,
+}
+
+impl Filter for GaussianBlur {
+    fn apply(&self, image: &mut RgbaImage) {
+        *image = gaussian_blur(image, self.radius);
+    }
+
+    fn name(&self) -> &'static str {
+        "filter-gaussian-blur"
+    }
+}
+
+// Two separable passes cost O(radius) per pixel, unlike the running-sum box blur. On a
+// 1024x1024 image (release) that is about 100 ms at radius 2 and 115 ms at radius 8, on par
+// with Blur, but about 410 ms at radius 32, where Blur stays near 110 ms.
+pub fn gaussian_blur(image: &RgbaImage, radius: f32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let kernel = gaussian_kernel(radius);
+    let reach = (kernel.len() / 2) as i64;
+
+    let premultiplied: Vec<[f32; 4]> = image
+        .pixels()
+        .map(|pixel| {
+            let alpha = pixel[3] as f32 / 255.0;
+            [pixel[0] as f32 * alpha, pixel[1] as f32 * alpha, pixel[2] as f32 * alpha, pixel[3] as f32]
+        })
+        .collect();
+    let pass = |source: &[[f32; 4]], horizontal: bool| {
+        let mut output = vec![[0.0f32; 4]; source.len()];
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let mut sum = [0.0f32; 4];
+                for (weight, offset) in kernel.iter().zip(-reach..=reach) {
+                    let (sample_x, sample_y) = if horizontal {
+                        ((x + offset).clamp(0, width as i64 - 1), y)
+                    } else {
+                        (x, (y + offset).clamp(0, height as i64 - 1))
+                    };
+                    let sample = source[(sample_y * width as i64 + sample_x) as usize];
+                    for channel in 0..4 {
+                        sum[channel] += sample[channel] * weight;
+                    }
+                }
+                output[(y * width as i64 + x) as usize] = sum;
+            }
+        }
+        output
+    };
+    let blurred = pass(&pass(&premultiplied, true), false);
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let [r, g, b, a] = blurred[(y * width + x) as usize];
+        if a <= 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let unpremultiply = |value: f32| (value * 255.0 / a).round().clamp(0.0, 255.0) as u8;
+        Rgba([unpremultiply(r), unpremultiply(g), unpremultiply(b), a.round() as u8])
+    })
+}
+
+// Normalized weights for offsets -reach..=reach, with sigma a third of the radius.
+fn gaussian_kernel(radius: f32) -> Vec<f32> {
+    let reach = radius.ceil().max(1.0) as i64;
+    let sigma = (radius / 3.0).max(0.5);
+    let mut kernel: Vec<f32> = (-reach..=reach)
+        .map(|offset| (-(offset * offset) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|weight| *weight /= total);
+    kernel
+}
+
+struct Sharpen;
+
+impl Filter for Sharpen {
+    fn apply(&self, image: &mut RgbaImage) {
+        *image = convolve_3x3(image, [[0.0, -1.0, 0.0], [-1.0, 5.0, -1.0], [0.0, -1.0, 0.0]]);
+    }
+
+    fn name(&self) -> &'static str {
+        "filter-sharpen"
+    }
+}
+
+struct EdgeDetect;
+
+impl Filter for EdgeDetect {
+    fn apply(&self, image: &mut RgbaImage) {
+        *image = sobel_edges(image);
+    }
+
+    fn name(&self) -> &'static str {
+        "filter-edge-detect"
+    }
+}
+
+pub fn sobel_edges(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let luma: Vec<f32> = image
+        .pixels()
+        .map(|pixel| 0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32)
+        .collect();
+    let sample = |x: i64, y: i64| {
+        let (x, y) = (x.clamp(0, width as i64 - 1), y.clamp(0, height as i64 - 1));
+        luma[(y * width as i64 + x) as usize]
+    };
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let horizontal = sample(x + 1, y - 1) + 2.0 * sample(x + 1, y) + sample(x + 1, y + 1)
+            - sample(x - 1, y - 1)
+            - 2.0 * sample(x - 1, y)
+            - sample(x - 1, y + 1);
+        let vertical = sample(x - 1, y + 1) + 2.0 * sample(x, y + 1) + sample(x + 1, y + 1)
+            - sample(x - 1, y - 1)
+            - 2.0 * sample(x, y - 1)
+            - sample(x + 1, y - 1);
+        let magnitude = horizontal.hypot(vertical).round().min(255.0) as u8;
+        Rgba([magnitude, magnitude, magnitude, 255])
+    })
+}
+
+pub fn convolve_3x3(image: &RgbaImage, kernel: [[f32; 3]; 3]) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn(width, height, |x, y| {
+        let mut sum = [0.0f32; 3];
+        for (row, weights) in kernel.iter().enumerate() {
+            let sample_y = (y as i64 + row as i64 - 1).clamp(0, height as i64 - 1) as u32;
+            for (column, weight) in weights.iter().enumerate() {
+                let sample_x = (x as i64 + column as i64 - 1).clamp(0, width as i64 - 1) as u32;
+                let sample = image.get_pixel(sample_x, sample_y);
+                for channel in 0..3 {
+                    sum[channel] += sample[channel] as f32 * weight;
+                }
+            }
+        }
+        let [r, g, b] = sum.map(|value| value.round().clamp(0.0, 255.0) as u8);
+        Rgba([r, g, b, image.get_pixel(x, y)[3]])
+    })
+}
+
+struct BrightnessContrast {
+    brightness: i32,
+    contrast: f32,
+}
+
+impl Filter for BrightnessContrast {
+    fn apply(&self, image: &mut RgbaImage) {
+        adjust_brightness_contrast(image, self.brightness, self.contrast);
+    }
+
+    fn name(&self) -> &'static str {
+        "filter-brightness-contrast"
+    }
+}
+
+pub fn adjust_brightness_contrast(image: &mut RgbaImage, brightness: i32, contrast: f32) {
+    let lookup: Vec<u8> = (0..=255)
+        .map(|value| {
+            let adjusted = (value as f32 - 128.0) * contrast + 128.0 + brightness as f32;
+            adjusted.round().clamp(0.0, 255.0) as u8
+        })
+        .collect();
+    for pixel in image.pixels_mut() {
+        for channel in 0..3 {
+            pixel[channel] = lookup[pixel[channel] as usize];
+        }
+    }
+}
+
+struct HueSaturation {
+    hue: f32,
+    saturation: f32,
+    lightness: f32,
+}
+
+impl Filter for HueSaturation {
+    fn apply(&self, image: &mut RgbaImage) {
+        adjust_hsl(image, self.hue, self.saturation, self.lightness);
+    }
+
+    fn name(&self) -> &'static str {
+        "filter-hsl"
+    }
+}
+
+// Hue is shifted in degrees and wraps around; saturation and lightness deltas are percentages
+// added to the pixel's own values.
+pub fn adjust_hsl(image: &mut RgbaImage, hue: f32, saturation: f32, lightness: f32) {
+    for pixel in image.pixels_mut() {
+        let (h, s, l) = rgb_to_hsl(pixel[0], pixel[1], pixel[2]);
+        let [r, g, b] = hsl_to_rgb(
+            (h + hue).rem_euclid(360.0),
+            (s + saturation / 100.0).clamp(0.0, 1.0),
+            (l + lightness / 100.0).clamp(0.0, 1.0),
+        );
+        *pixel = Rgba([r, g, b, pixel[3]]);
+    }
+}
+
+fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0, lightness);
+    }
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (hue, saturation, lightness)
+}
+
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> [u8; 3] {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let offset = lightness - chroma / 2.0;
+    [r, g, b].map(|value| ((value + offset) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+struct Threshold {
+    level: u8,
+}
+
+impl Filter for Threshold {
+    fn apply(&self, image: &mut RgbaImage) {
+        apply_threshold(image, self.level);
+    }
+
+    fn name(&self) -> &'static str {
+        "filter-threshold"
+    }
+}
+
+pub fn apply_threshold(image: &mut RgbaImage, level: u8) {
+    for pixel in image.pixels_mut() {
+        let luma = 0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32;
+        let value = if luma > level as f32 { 255 } else { 0 };
+        *pixel = Rgba([value, value, value, pixel[3]]);
+    }
+}
+
+struct Posterize {
+    levels: u32,
+}
+
+impl Filter for Posterize {
+    fn apply(&self, image: &mut RgbaImage) {
+        posterize(image, self.levels);
+    }
+
+    fn name(&self) -> &'static str {
+        "filter-posterize"
+    }
+}
+
+// Fewer than two levels cannot represent anything and 256 or more keeps every value,
+// so both leave the image untouched.
+pub fn posterize(image: &mut RgbaImage, levels: u32) {
+    if !(2..256).contains(&levels) {
+        return;
+    }
+    let step = 255.0 / (levels - 1) as f32;
+    let lookup: Vec<u8> = (0..=255)
+        .map(|value| ((value as f32 / step).round() * step).round() as u8)
+        .collect();
+    for pixel in image.pixels_mut() {
+        for channel in 0..3 {
+            pixel[channel] = lookup[pixel[channel] as usize];
+        }
+    }
+}
+
+fn blur_image(image: &mut RgbaImage, radius: u32) {
+    let (width, height) = image.dimensions();
+    let alpha: Vec<f32> = image.pixels().map(|pixel| pixel[3] as f32).collect();
+    let blurred_alpha = box_blur_mask(&alpha, width, height, radius);
+    let blurred_colors: Vec<Vec<f32>> = (0..3)
+        .map(|channel| {
+            let premultiplied: Vec<f32> = image
+                .pixels()
+                .map(|pixel| pixel[channel] as f32 * pixel[3] as f32 / 255.0)
+                .collect();
+            box_blur_mask(&premultiplied, width, height, radius)
+        })
+        .collect();
+    for (index, pixel) in image.pixels_mut().enumerate() {
+        let alpha = blurred_alpha[index];
+        if alpha <= 0.0 {
+            *pixel = Rgba([0, 0, 0, 0]);
+            continue;
+        }
+        let channel = |values: &Vec<f32>| (values[index] * 255.0 / alpha).round().clamp(0.0, 255.0) as u8;
+        *pixel = Rgba([
+            channel(&blurred_colors[0]),
+            channel(&blurred_colors[1]),
+            channel(&blurred_colors[2]),
+            alpha.round() as u8,
+        ]);
+    }
+}
+
+pub fn box_blur_mask(mask: &[f32], width: u32, height: u32, radius: u32) -> Vec<f32> {
+    let (width, height, radius) = (width as usize, height as usize, radius as i64);
+    let window = (radius * 2 + 1) as f32;
+    let mut horizontal = vec![0.0f32; mask.len()];
+    for y in 0..height {
+        let row = &mask[y * width..(y + 1) * width];
+        let sample = |x: i64| row[x.clamp(0, width as i64 - 1) as usize];
+        let mut sum: f32 = (-radius..=radius).map(sample).sum();
+        for x in 0..width {
+            horizontal[y * width + x] = sum / window;
+            sum += sample(x as i64 + radius + 1) - sample(x as i64 - radius);
+        }
+    }
+    let mut blurred = vec![0.0f32; mask.len()];
+    for x in 0..width {
+        let sample = |y: i64| horizontal[y.clamp(0, height as i64 - 1) as usize * width + x];
+        let mut sum: f32 = (-radius..=radius).map(sample).sum();
+        for y in 0..height {
+            blurred[y * width + x] = sum / window;
+            sum += sample(y as i64 + radius + 1) - sample(y as i64 - radius);
+        }
+    }
+    blurred
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    #[test]
+    fn gaussian_kernel_is_normalized_and_symmetric() {
+        for radius in [0.0, 1.0, 2.5, 8.0, 30.0] {
+            let kernel = gaussian_kernel(radius);
+            assert_eq!(kernel.len() % 2, 1);
+            assert!((kernel.iter().sum::<f32>() - 1.0).abs() < 1e-5, "radius {}", radius);
+            assert!(kernel.iter().eq(kernel.iter().rev()));
+        }
+    }
+
+    #[test]
+    fn gaussian_blur_spreads_a_point_symmetrically() {
+        let mut image = RgbaImage::from_pixel(9, 9, BLACK);
+        image.put_pixel(4, 4, WHITE);
+
+        let blurred = gaussian_blur(&image, 3.0);
+
+        let center = blurred.get_pixel(4, 4)[0];
+        assert!(center > 0 && center < 255);
+        for (dx, dy) in [(1, 0), (2, 1), (3, 3)] {
+            let value = blurred.get_pixel(4 + dx, 4 + dy)[0];
+            assert!(value < center);
+            for (x, y) in [(4 - dx, 4 + dy), (4 + dx, 4 - dy), (4 - dx, 4 - dy), (4 + dy, 4 + dx)] {
+                assert_eq!(blurred.get_pixel(x, y)[0], value, "({}, {})", x, y);
+            }
+        }
+        assert!(blurred.pixels().all(|pixel| pixel[3] == 255));
+    }
+
+    #[test]
+    fn gaussian_blur_leaves_a_flat_image_unchanged() {
+        let image = RgbaImage::from_pixel(12, 7, Rgba([40, 120, 200, 180]));
+        assert_eq!(gaussian_blur(&image, 4.0), image);
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture` to compare the separable
+    // Gaussian against the running-sum box blur.
+    #[test]
+    #[ignore]
+    fn time_gaussian_blur_against_box_blur() {
+        let image = RgbaImage::from_fn(1024, 1024, |x, y| Rgba([(x % 256) as u8, (y % 256) as u8, 128, 255]));
+        for radius in [2, 8, 32] {
+            let started = Instant::now();
+            gaussian_blur(&image, radius as f32);
+            let gaussian = started.elapsed();
+            let mut boxed = image.clone();
+            let started = Instant::now();
+            blur_image(&mut boxed, radius);
+            let box_blur = started.elapsed();
+            println!("radius {}: gaussian {:?}, box {:?}", radius, gaussian, box_blur);
+        }
+    }
+
+    #[test]
+    fn threshold_splits_on_luma_and_keeps_alpha() {
+        let mut image = RgbaImage::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([100, 100, 100, 200]),
+            1 => Rgba([101, 101, 101, 200]),
+            _ => Rgba([255, 0, 0, 255]),
+        });
+
+        apply_threshold(&mut image, 100);
+
+        assert_eq!(*image.get_pixel(0, 0), Rgba([0, 0, 0, 200]), "a luma equal to the level is black");
+        assert_eq!(*image.get_pixel(1, 0), Rgba([255, 255, 255, 200]));
+        assert_eq!(*image.get_pixel(2, 0), BLACK, "pure red has a luma of about 76");
+    }
+
+    #[test]
+    fn posterize_ignores_unusable_levels_and_snaps_to_steps() {
+        let ramp = RgbaImage::from_fn(256, 1, |x, _| Rgba([x as u8, 255 - x as u8, 0, 90]));
+        for levels in [0, 1, 256, 1000] {
+            let mut image = ramp.clone();
+            posterize(&mut image, levels);
+            assert_eq!(image, ramp, "{} levels", levels);
+        }
+
+        let mut image = ramp.clone();
+        posterize(&mut image, 2);
+        assert!(image.pixels().all(|pixel| [0, 255].contains(&pixel[0]) && pixel[3] == 90));
+        assert_eq!(image.get_pixel(127, 0)[0], 0);
+        assert_eq!(image.get_pixel(128, 0)[0], 255);
+
+        let mut image = ramp.clone();
+        posterize(&mut image, 4);
+        let mut values: Vec<u8> = image.pixels().map(|pixel| pixel[0]).collect();
+        values.dedup();
+        assert_eq!(values, [0, 85, 170, 255]);
+    }
+
+    #[test]
+    fn sepia_applies_the_tone_matrix_and_clamps() {
+        let mut image = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { Rgba([100, 50, 20, 77]) } else { WHITE });
+
+        apply_sepia(&mut image);
+
+        assert_eq!(*image.get_pixel(0, 0), Rgba([82, 73, 57, 77]));
+        assert_eq!(*image.get_pixel(1, 0), Rgba([255, 255, 239, 255]));
+    }
+
+    #[test]
+    fn hsl_rotates_hue_and_shifts_saturation_and_lightness() {
+        let mut image = RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 128]));
+        adjust_hsl(&mut image, 180.0, 0.0, 0.0);
+        assert_eq!(*image.get_pixel(0, 0), Rgba([0, 255, 255, 128]));
+
+        let mut image = RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 255]));
+        adjust_hsl(&mut image, -120.0, 0.0, 0.0);
+        assert_eq!(*image.get_pixel(0, 0), Rgba([0, 0, 255, 255]), "negative shifts wrap around");
+
+        let mut image = RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 255]));
+        adjust_hsl(&mut image, 0.0, -100.0, 0.0);
+        assert_eq!(*image.get_pixel(0, 0), Rgba([128, 128, 128, 255]));
+
+        let mut image = RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 255]));
+        adjust_hsl(&mut image, 0.0, 0.0, 100.0);
+        assert_eq!(*image.get_pixel(0, 0), WHITE);
+    }
+
+    #[test]
+    fn sharpen_keeps_flat_areas_and_boosts_edges() {
+        let flat = RgbaImage::from_pixel(5, 5, Rgba([90, 140, 30, 200]));
+        let mut image = flat.clone();
+        Sharpen.apply(&mut image);
+        assert_eq!(image, flat);
+
+        let mut image = RgbaImage::from_fn(6, 3, |x, _| {
+            let value = if x < 3 { 100 } else { 150 };
+            Rgba([value, value, value, 255])
+        });
+        Sharpen.apply(&mut image);
+        assert_eq!(image.get_pixel(2, 1)[0], 50);
+        assert_eq!(image.get_pixel(3, 1)[0], 200);
+        assert_eq!(image.get_pixel(0, 1)[0], 100);
+    }
+
+    #[test]
+    fn sobel_is_zero_on_flat_images_and_strong_on_a_vertical_step() {
+        let flat = RgbaImage::from_pixel(5, 5, Rgba([60, 180, 90, 255]));
+        assert!(sobel_edges(&flat).pixels().all(|&pixel| pixel == BLACK));
+
+        let step = RgbaImage::from_fn(8, 5, |x, _| if x < 4 { BLACK } else { WHITE });
+        let edges = sobel_edges(&step);
+        for y in 0..5 {
+            assert_eq!(*edges.get_pixel(3, y), WHITE);
+            assert_eq!(*edges.get_pixel(4, y), WHITE);
+            assert_eq!(*edges.get_pixel(1, y), BLACK);
+            assert_eq!(*edges.get_pixel(6, y), BLACK);
+        }
+    }
+
+    #[test]
+    fn brightness_and_contrast_shift_and_stretch_around_mid_gray() {
+        let original = RgbaImage::from_fn(3, 1, |x, _| Rgba([[100, 200, 250][x as usize], 0, 128, 60]));
+        let adjusted = |brightness, contrast| {
+            let mut image = original.clone();
+            adjust_brightness_contrast(&mut image, brightness, contrast);
+            image.pixels().map(|pixel| pixel[0]).collect::<Vec<_>>()
+        };
+
+        assert_eq!(adjusted(0, 1.0), [100, 200, 250]);
+        assert_eq!(adjusted(50, 1.0), [150, 250, 255]);
+        assert_eq!(adjusted(-120, 1.0), [0, 80, 130]);
+        assert_eq!(adjusted(0, 2.0), [72, 255, 255]);
+        assert_eq!(adjusted(0, 0.0), [128, 128, 128]);
+
+        let mut image = original.clone();
+        adjust_brightness_contrast(&mut image, 30, 1.5);
+        assert!(image.pixels().all(|pixel| pixel[3] == 60 && pixel[2] == 158));
+    }
+}
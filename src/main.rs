@@ -1,50 +1,737 @@
-use druid::widget::{Align, Button, Flex, Label, Painter, TextBox, WidgetExt};
+mod filters;
+mod palette;
+mod settings;
+
+use druid::widget::{Align, Button, Checkbox, Either, Flex, Label, Painter, RadioGroup, Slider, TextBox, WidgetExt};
 use druid::{
-    AppLauncher, Color, Data, Event, EventCtx, KeyEvent, Lens, LocalizedString, Point, Rect, RenderContext,
-    Widget, WindowDesc, Code,
+    AppDelegate, AppLauncher, Application, Color, Command, Cursor, CursorDesc, Data, DelegateCtx, Env, Event, EventCtx,
+    FileDialogOptions, FileInfo, MouseButton, FileSpec, Handled, ImageBuf, KeyEvent, Lens, LifeCycle, LifeCycleCtx, LocalizedString,
+    PaintCtx, Point, Rect, RenderContext, Selector, Size, Target, UpdateCtx, Vec2, Widget, WindowDesc, WindowHandle, Code,
 };
 use druid::widget::Controller;
-use druid::piet::ImageFormat;
-use image::{Rgba, RgbaImage};
-use image::imageops::replace;
-use std::sync::{Arc, RwLock};
+use druid::lens;
+use druid::kurbo::{BezPath, Ellipse, Line};
+use druid::piet::{ImageFormat, PietImage, StrokeStyle, Text, TextLayout, TextLayoutBuilder};
+use image::{ColorType, DynamicImage, GrayAlphaImage, GrayImage, ImageDecoder, ImageError, Pixel, RgbImage, Rgba, RgbaImage};
+use image::codecs::bmp::BmpDecoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngDecoder;
+use image::imageops::{
+    crop_imm, flip_horizontal, flip_vertical, overlay, resize, rotate180, rotate270, rotate90, FilterType,
+};
+use image::io::Reader as ImageReader;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
 use std::time::{Duration, Instant};
 
-#[derive(Clone, Data, PartialEq)]
+use filters::{Filter, FilterParams, box_blur_mask};
+use palette::{default_palette, load_gpl, save_gpl};
+use settings::{Settings, parse_rgb};
+
+const CANVAS_WIDTH: f64 = 800.0;
+const CANVAS_HEIGHT: f64 = 600.0;
+const MAX_CANVAS_DIMENSION: u32 = 8192;
+const MINIMAP_WIDTH: f64 = 180.0;
+const MINIMAP_HEIGHT: f64 = 135.0;
+const APP_BACKDROP: Color = Color::rgb8(60, 60, 60);
+
+const ERASER_CURSOR: [&str; 16] = [
+    "################",
+    "#..............#",
+    "#.            .#",
+    "#.            .#",
+    "#.            .#",
+    "#.            .#",
+    "#.            .#",
+    "#.            .#",
+    "#.            .#",
+    "#.            .#",
+    "#.            .#",
+    "#.            .#",
+    "#.            .#",
+    "#.            .#",
+    "#..............#",
+    "################",
+];
+const BUCKET_CURSOR: [&str; 16] = [
+    "     ##         ",
+    "    #..#        ",
+    "   #.##.#       ",
+    "  #.#  #.#      ",
+    " #..#  #..#     ",
+    "#....##....#    ",
+    "#..........#    ",
+    " #........#.#   ",
+    "  #......#..#   ",
+    "   #....#...#   ",
+    "    #..#  #.#   ",
+    "     ##   #.#   ",
+    "          #.#   ",
+    "           #    ",
+    "                ",
+    "                ",
+];
+const EYEDROPPER_CURSOR: [&str; 16] = [
+    "            ##  ",
+    "           #..# ",
+    "          #....#",
+    "         #.....#",
+    "        #.....# ",
+    "         #...#  ",
+    "        #.#.#   ",
+    "       #.# #    ",
+    "      #.#       ",
+    "     #.#        ",
+    "    #.#         ",
+    "   #.#          ",
+    "  #.#           ",
+    " #.#            ",
+    "#.#             ",
+    "##              ",
+];
+
+const MIN_ZOOM: f64 = 0.1;
+const MAX_ZOOM: f64 = 16.0;
+const ZOOM_STEP: f64 = 1.25;
+const PAN_MARGIN: f64 = 32.0;
+const SPRAY_SEED: u64 = 0x5eed;
+const POLYGON_CLOSE_DISTANCE: f64 = 8.0;
+const SWATCH_SIZE: f64 = 22.0;
+const SWATCHES_PER_ROW: usize = 8;
+const MAX_RECENT_COLORS: usize = SWATCHES_PER_ROW;
+const MAX_CUSTOM_SWATCHES: usize = 16;
+const MAX_PALETTE_SWATCHES: usize = 32;
+const MAX_BRUSH_SIZE: u32 = 1000;
+const LOUPE_RADIUS: u32 = 7;
+const LOUPE_SCALE: f64 = 8.0;
+// Largest radius the 64px brush preview renders; bigger brushes are shown scaled down to it.
+const BRUSH_PREVIEW_RADIUS: u32 = 31;
+type CircleMaskKey = (i32, bool, u32);
+static CIRCLE_MASK_CACHE: Mutex<Option<(CircleMaskKey, Arc<Vec<f32>>)>> = Mutex::new(None);
+static SQUARE_MASK_CACHE: Mutex<Option<(u32, Arc<Vec<f32>>)>> = Mutex::new(None);
+// Keyed by the source stamp itself so a replaced stamp never matches a stale entry at a reused address.
+type StampMaskKey = (i32, StampInterp, Arc<RgbaImage>);
+type ScaledStamp = (Arc<RgbaImage>, Arc<Vec<f32>>);
+static STAMP_MASK_CACHE: Mutex<Option<(StampMaskKey, ScaledStamp)>> = Mutex::new(None);
+
+const MAX_UNDO_STEPS: usize = 20;
+const UNDO_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(2);
+const UNDO_CHECKPOINT_DISTANCE: f64 = 400.0;
+const MIN_PAINT_INTERVAL_MS: u64 = 8;
+// Pointer speed, in image pixels per millisecond, at which a dynamic brush is half its size.
+const DYNAMIC_SIZE_SPEED: f64 = 1.0;
+const MAX_PAINT_INTERVAL_MS: u64 = 50;
+
+const LOAD_STAMP: Selector<FileInfo> = Selector::new("photoshop-mvp.load-stamp");
+const OPEN_IMAGE: Selector<FileInfo> = Selector::new("photoshop-mvp.open-image");
+const SAVE_IMAGE: Selector<FileInfo> = Selector::new("photoshop-mvp.save-image");
+const EXPORT_TILES: Selector<FileInfo> = Selector::new("photoshop-mvp.export-tiles");
+const EXPORT_SELECTION: Selector<FileInfo> = Selector::new("photoshop-mvp.export-selection");
+const EXPORT_VIEW: Selector<FileInfo> = Selector::new("photoshop-mvp.export-view");
+const LOAD_PALETTE: Selector<FileInfo> = Selector::new("photoshop-mvp.load-palette");
+const SAVE_PALETTE: Selector<FileInfo> = Selector::new("photoshop-mvp.save-palette");
+const EXPORT_CHANNEL: Selector<FileInfo> = Selector::new("photoshop-mvp.export-channel");
+const IMAGE_OPENED: Selector<(PathBuf, OpenResult)> = Selector::new("photoshop-mvp.image-opened");
+const CANCEL_SHAPE: Selector = Selector::new("photoshop-mvp.cancel-shape");
+const URL_IMAGE_LOADED: Selector<Result<RgbaImage, String>> = Selector::new("photoshop-mvp.url-image-loaded");
+const URL_TIMEOUT: Duration = Duration::from_secs(5);
+
+type OpenResult = Result<(RgbaImage, Option<ImageError>), ImageError>;
+type CanvasCacheKey = (Arc<Vec<Layer>>, u64, Option<(usize, FilterParams)>);
+type CachedImage = (CanvasCacheKey, PietImage);
+type CachedMinimap = (Arc<Vec<Layer>>, u64, PietImage);
+type CachedBrushPreview = (BrushPreviewKey, PietImage);
+
+#[derive(Clone, Data, PartialEq, Eq, Hash)]
 enum Tool {
     Brush,
     Eraser,
+    Polygon,
+    Fill,
+    Select,
+    Line,
+    Rectangle,
+    Ellipse,
+    Picker,
+    Smudge,
+    Crop,
+    Spray,
+    Gradient,
+    Pencil,
+}
+
+#[derive(Clone, Copy, Data, PartialEq)]
+enum GradientMode {
+    Linear,
+    Radial,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum StampMode {
+    Deposit,
+    Remove,
+    Smudge,
 }
 
 #[derive(Clone, Data, PartialEq)]
 enum BrushShape {
     Square,
     Circle,
+    Stamp,
+}
+
+#[derive(Clone, Data, PartialEq)]
+enum EraseMode {
+    Transparent,
+    Background,
+    Secondary,
+}
+
+#[derive(Clone, Copy, Data, PartialEq)]
+enum Symmetry {
+    None,
+    Vertical,
+    Horizontal,
+    Both,
+}
+
+#[derive(Clone, Data, PartialEq)]
+enum StampInterp {
+    Nearest,
+    Bilinear,
+}
+
+#[derive(Clone, Copy, Data, PartialEq)]
+enum NewBackground {
+    White,
+    Transparent,
+    Custom,
+}
+
+#[derive(Clone, Copy, Data, PartialEq)]
+enum FalloffCurve {
+    Linear,
+    EaseIn,
+    EaseOut,
+}
+
+#[derive(Clone, Copy, Data, PartialEq)]
+enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+#[derive(Clone, Copy, Data, PartialEq)]
+enum ChannelSource {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Zero,
+    Full,
+}
+
+#[derive(Clone, Data, Default)]
+struct FrameStats {
+    fps: f64,
+    last_stroke_ms: f64,
+}
+
+// The brush settings the preview thumbnail depends on; it is only re-rendered when these change.
+#[derive(Clone, Data)]
+struct BrushPreviewKey {
+    pencil: bool,
+    radius: u32,
+    color: Color,
+    antialiasing: bool,
+    opacity: f64,
+    flow: f64,
+    hardness: f64,
+    shape: BrushShape,
+    stamp: Option<Arc<RgbaImage>>,
+    stamp_interp: StampInterp,
+    angle: f64,
+    rotate_to_direction: bool,
+    falloff_curve: FalloffCurve,
+}
+
+// A status bar message kept as a Fluent key and its arguments, so it is translated
+// when the status bar shows it rather than when it is set.
+#[derive(Clone, Data, Debug, Default, PartialEq)]
+struct StatusMessage {
+    key: &'static str,
+    args: Arc<Vec<(&'static str, String)>>,
+}
+
+impl StatusMessage {
+    fn new(key: &'static str) -> Self {
+        StatusMessage {
+            key,
+            args: Arc::new(Vec::new()),
+        }
+    }
+
+    fn with_arg(mut self, name: &'static str, value: impl ToString) -> Self {
+        Arc::make_mut(&mut self.args).push((name, value.to_string()));
+        self
+    }
+
+    fn localize(&self, env: &Env) -> String {
+        if self.key.is_empty() {
+            return String::new();
+        }
+        let mut text = LocalizedString::new(self.key);
+        for (name, value) in self.args.iter() {
+            let value = value.clone();
+            text = text.with_arg(name, move |_: &(), _env| value.clone().into());
+        }
+        text.resolve(&(), env);
+        text.localized_str().to_string()
+    }
 }
 
 #[derive(Clone, Data, Lens)]
 struct AppState {
     image: Arc<RwLock<RgbaImage>>,
+    layers: Arc<Vec<Layer>>,
+    active_layer: usize,
     brush_color: Color,
+    secondary_color: Color,
+    gradient_end_color: Color,
+    gradient_end_input: String,
+    gradient_mode: GradientMode,
     is_drawing: bool,
     brush_size: u32,
+    brush_antialiasing: bool,
+    brush_opacity: f64,
+    brush_flow: f64,
+    brush_hardness: f64,
+    brush_step: f64,
+    spray_density: f64,
     current_tool: Tool,
+    tool_settings: Arc<HashMap<Tool, ToolSettings>>,
+    previous_tool: Tool,
+    erase_mode: EraseMode,
     brush_shape: BrushShape,
+    brush_stamp: Option<Arc<RgbaImage>>,
+    brush_angle: f64,
+    rotate_to_direction: bool,
+    dynamic_size: bool,
+    falloff_curve: FalloffCurve,
+    symmetry: Symmetry,
+    polygon_points: Arc<Vec<Point>>,
+    fill_tolerance: u32,
+    fill_tolerance_input: String,
+    fill_feather: u32,
+    fill_feather_input: String,
+    cursor_pos: Option<Point>,
+    stroke: Option<Arc<Mutex<StrokeBuffer>>>,
+    erase_stroke: Option<Arc<Mutex<EraseBuffer>>>,
+    smudge_carry: Option<Arc<Mutex<RgbaImage>>>,
+    selection: Option<Arc<Selection>>,
+    selection_drag: Option<Rect>,
+    clipboard: Option<Arc<RgbaImage>>,
+    floating: Option<Arc<RgbaImage>>,
+    floating_origin: Point,
+    shape_start: Option<Point>,
+    shape_end: Point,
+    shape_fill: bool,
+    picker_sample_size: u32,
+    picker_switch_back: bool,
+    picker_merged: bool,
+    history: Arc<History>,
+    split_long_strokes: bool,
+    stamp_interp: StampInterp,
     brush_size_input: String,
     color_r_input: String,
     color_g_input: String,
     color_b_input: String,
+    color_a_input: String,
+    color_hex_input: String,
     background_color: Color,
+    new_background: NewBackground,
+    new_width_input: String,
+    new_height_input: String,
+    resize_width_input: String,
+    resize_height_input: String,
+    resize_filter: ResizeFilter,
+    resize_lock_aspect: bool,
+    rotate_degrees_input: String,
+    palette: Arc<Vec<(Color, String)>>,
+    custom_palette: Arc<Vec<Color>>,
+    recent_colors: Arc<Vec<Color>>,
+    remap_input: String,
+    export_premultiplied: bool,
+    jpeg_quality: f64,
+    tile_width_input: String,
+    tile_height_input: String,
+    tile_margin_input: String,
+    tile_spacing_input: String,
+    export_channel: Channel,
+    pack_red: ChannelSource,
+    pack_green: ChannelSource,
+    pack_blue: ChannelSource,
+    pack_alpha: ChannelSource,
+    blur_radius: f64,
+    brightness: f64,
+    threshold: f64,
+    posterize_levels: f64,
+    hue_shift: f64,
+    saturation_shift: f64,
+    lightness_shift: f64,
+    contrast: f64,
+    last_filter: Option<Arc<dyn Filter>>,
+    filter_preview: Option<usize>,
+    status_message: StatusMessage,
+    url_input: String,
+    clear_pending: bool,
+    show_stats: bool,
+    show_loupe: bool,
+    show_dirty_rect: bool,
+    stroke_dirty: Option<Rect>,
+    stats: FrameStats,
+    zoom: f64,
+    zoom_input: String,
+    pan_offset: Point,
+    canvas_width: f64,
+    canvas_height: f64,
+    image_generation: u64,
+    dirty: bool,
+    exit_pending: bool,
+    exit_after_save: bool,
+}
+
+impl AppState {
+    fn new(image: Arc<RwLock<RgbaImage>>, settings: Settings) -> Self {
+        let (r, g, b, a) = settings.default_brush_color.as_rgba8();
+        let layers = Arc::new(vec![Layer {
+            name: "Background".to_string(),
+            image: image.clone(),
+            visible: true,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+        }]);
+        AppState {
+            image,
+            layers,
+            active_layer: 0,
+            brush_color: settings.default_brush_color,
+            secondary_color: Color::WHITE,
+            gradient_end_color: Color::WHITE,
+            gradient_end_input: format_hex_color(Color::WHITE),
+            gradient_mode: GradientMode::Linear,
+            is_drawing: false,
+            brush_size: 5,
+            brush_antialiasing: false,
+            brush_opacity: 1.0,
+            brush_flow: 1.0,
+            brush_hardness: 1.0,
+            brush_step: 0.5,
+            spray_density: 20.0,
+            current_tool: Tool::Brush,
+            tool_settings: Arc::new(HashMap::new()),
+            previous_tool: Tool::Brush,
+            erase_mode: EraseMode::Transparent,
+            brush_shape: BrushShape::Square,
+            brush_stamp: None,
+            brush_angle: 0.0,
+            rotate_to_direction: false,
+            dynamic_size: false,
+            falloff_curve: FalloffCurve::Linear,
+            symmetry: Symmetry::None,
+            polygon_points: Arc::new(Vec::new()),
+            fill_tolerance: 0,
+            fill_tolerance_input: "0".to_string(),
+            fill_feather: 0,
+            fill_feather_input: "0".to_string(),
+            cursor_pos: None,
+            stroke: None,
+            erase_stroke: None,
+            smudge_carry: None,
+            selection: None,
+            selection_drag: None,
+            clipboard: None,
+            floating: None,
+            floating_origin: Point::ZERO,
+            shape_start: None,
+            shape_end: Point::ZERO,
+            shape_fill: false,
+            picker_sample_size: 1,
+            picker_switch_back: false,
+            picker_merged: true,
+            history: Arc::new(History::default()),
+            split_long_strokes: false,
+            stamp_interp: StampInterp::Nearest,
+            brush_size_input: "5".to_string(),
+            color_r_input: r.to_string(),
+            color_g_input: g.to_string(),
+            color_b_input: b.to_string(),
+            color_a_input: a.to_string(),
+            color_hex_input: format_hex_color(settings.default_brush_color),
+            background_color: Color::WHITE,
+            new_background: NewBackground::White,
+            new_width_input: "800".to_string(),
+            new_height_input: "600".to_string(),
+            resize_width_input: "800".to_string(),
+            resize_height_input: "600".to_string(),
+            resize_filter: ResizeFilter::Triangle,
+            resize_lock_aspect: true,
+            rotate_degrees_input: String::new(),
+            palette: Arc::new(default_palette()),
+            custom_palette: Arc::new(settings.custom_palette),
+            recent_colors: Arc::new(Vec::new()),
+            remap_input: String::new(),
+            export_premultiplied: false,
+            jpeg_quality: 90.0,
+            tile_width_input: "32".to_string(),
+            tile_height_input: "32".to_string(),
+            tile_margin_input: "0".to_string(),
+            tile_spacing_input: "0".to_string(),
+            export_channel: Channel::Alpha,
+            pack_red: ChannelSource::Red,
+            pack_green: ChannelSource::Green,
+            pack_blue: ChannelSource::Blue,
+            pack_alpha: ChannelSource::Alpha,
+            blur_radius: 2.0,
+            brightness: 0.0,
+            threshold: 128.0,
+            posterize_levels: 4.0,
+            hue_shift: 0.0,
+            saturation_shift: 0.0,
+            lightness_shift: 0.0,
+            contrast: 1.0,
+            last_filter: None,
+            filter_preview: None,
+            status_message: StatusMessage::default(),
+            url_input: String::new(),
+            clear_pending: false,
+            show_stats: false,
+            show_loupe: false,
+            show_dirty_rect: false,
+            stroke_dirty: None,
+            stats: FrameStats::default(),
+            zoom: 1.0,
+            zoom_input: "100".to_string(),
+            pan_offset: Point::ZERO,
+            canvas_width: CANVAS_WIDTH,
+            canvas_height: CANVAS_HEIGHT,
+            image_generation: 0,
+            dirty: false,
+            exit_pending: false,
+            exit_after_save: false,
+        }
+    }
+}
+
+struct Selection {
+    mask: Vec<f32>,
+    bounds: Option<Rect>,
+}
+
+impl Selection {
+    fn from_mask(mask: Vec<f32>, width: u32) -> Self {
+        let mut bounds: Option<Rect> = None;
+        for (index, strength) in mask.iter().enumerate() {
+            if *strength < 0.5 {
+                continue;
+            }
+            let (x, y) = ((index as u32 % width) as f64, (index as u32 / width) as f64);
+            let pixel = Rect::new(x, y, x + 1.0, y + 1.0);
+            bounds = Some(bounds.map_or(pixel, |bounds| bounds.union(pixel)));
+        }
+        Selection { mask, bounds }
+    }
+
+    fn rectangle(rect: Rect, width: u32, height: u32) -> Self {
+        let mask = (0..width * height)
+            .map(|index| {
+                let point = Point::new((index % width) as f64 + 0.5, (index / width) as f64 + 0.5);
+                if rect.contains(point) { 1.0 } else { 0.0 }
+            })
+            .collect();
+        Selection::from_mask(mask, width)
+    }
+}
+
+#[derive(Clone, Default)]
+struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+#[derive(Clone)]
+enum Snapshot {
+    Pixels {
+        layer: Arc<RwLock<RgbaImage>>,
+        image: Arc<RgbaImage>,
+    },
+    Layers {
+        layers: Arc<Vec<Layer>>,
+        active_layer: usize,
+    },
+}
+
+impl Snapshot {
+    fn size_bytes(&self) -> usize {
+        match self {
+            Snapshot::Pixels { image, .. } => image.as_raw().len(),
+            Snapshot::Layers { .. } => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Data, PartialEq)]
+enum ResizeFilter {
+    Nearest,
+    Triangle,
+    Lanczos3,
+}
+
+#[derive(Clone, Copy, Data, PartialEq)]
+enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Add,
+}
+
+#[derive(Clone)]
+struct Layer {
+    name: String,
+    image: Arc<RwLock<RgbaImage>>,
+    visible: bool,
+    opacity: f64,
+    blend_mode: BlendMode,
+}
+
+impl Layer {
+    fn new(name: String, image: RgbaImage) -> Self {
+        Layer {
+            name,
+            image: Arc::new(RwLock::new(image)),
+            visible: true,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ToolSettings {
+    size: u32,
+    opacity: f64,
+    flow: f64,
+    hardness: f64,
+}
+
+struct StrokeBuffer {
+    layer: RgbaImage,
+    coverage: Vec<f32>,
+}
+
+impl StrokeBuffer {
+    fn new(width: u32, height: u32) -> Self {
+        StrokeBuffer {
+            layer: RgbaImage::new(width, height),
+            coverage: vec![0.0; (width * height) as usize],
+        }
+    }
+
+    fn composite(&self, image: &mut RgbaImage) {
+        for (pixel, stroke_pixel) in image.pixels_mut().zip(self.layer.pixels()) {
+            if stroke_pixel[3] > 0 {
+                blend_pixel(pixel, *stroke_pixel);
+            }
+        }
+    }
+}
+
+struct EraseBuffer {
+    base: RgbaImage,
+    coverage: Vec<f32>,
+}
+
+struct Dab {
+    x_origin: i32,
+    y_origin: i32,
+    width: u32,
+    height: u32,
+    coverage: Arc<Vec<f32>>,
+    colors: Option<Arc<RgbaImage>>,
 }
 
 struct CanvasController {
     last_paint: Instant,
+    eraser_cursor: Option<Cursor>,
+    fill_cursor: Option<Cursor>,
+    picker_cursor: Option<Cursor>,
+    stroke_time: Duration,
+    checkpoint_time: Instant,
+    checkpoint_distance: f64,
+    last_pos: Option<Point>,
+    last_move: Instant,
+    select_start: Option<Point>,
+    move_anchor: Option<Vec2>,
+    pan_anchor: Option<Point>,
+    space_held: bool,
+    spray_rng: StdRng,
 }
 
 impl CanvasController {
     fn new() -> Self {
         CanvasController {
             last_paint: Instant::now(),
+            eraser_cursor: None,
+            fill_cursor: None,
+            picker_cursor: None,
+            stroke_time: Duration::ZERO,
+            checkpoint_time: Instant::now(),
+            checkpoint_distance: 0.0,
+            last_pos: None,
+            last_move: Instant::now(),
+            select_start: None,
+            move_anchor: None,
+            pan_anchor: None,
+            space_held: false,
+            spray_rng: StdRng::seed_from_u64(SPRAY_SEED),
+        }
+    }
+
+    fn cursor_for_tool(&mut self, window: &WindowHandle, tool: &Tool) -> Cursor {
+        match tool {
+            Tool::Brush
+            | Tool::Polygon
+            | Tool::Select
+            | Tool::Line
+            | Tool::Rectangle
+            | Tool::Ellipse
+            | Tool::Smudge
+            | Tool::Crop
+            | Tool::Spray
+            | Tool::Gradient
+            | Tool::Pencil => Cursor::Crosshair,
+            Tool::Eraser => self
+                .eraser_cursor
+                .get_or_insert_with(|| make_bitmap_cursor(window, &ERASER_CURSOR, (8.0, 8.0)))
+                .clone(),
+            Tool::Fill => self
+                .fill_cursor
+                .get_or_insert_with(|| make_bitmap_cursor(window, &BUCKET_CURSOR, (11.0, 13.0)))
+                .clone(),
+            Tool::Picker => self
+                .picker_cursor
+                .get_or_insert_with(|| make_bitmap_cursor(window, &EYEDROPPER_CURSOR, (1.0, 14.0)))
+                .clone(),
         }
     }
 }
@@ -59,19 +746,293 @@ impl<W: Widget<AppState>> Controller<AppState, W> for CanvasController {
         env: &druid::Env,
     ) {
         let now = Instant::now();
-        let should_paint = now.duration_since(self.last_paint) >= Duration::from_millis(16); // ~60 FPS
+        let should_paint = now.duration_since(self.last_paint) >= paint_interval(data.brush_size);
+
+        if let Event::MouseMove(mouse_event) = event {
+            let cursor = if self.space_held || self.pan_anchor.is_some() {
+                Cursor::Pointer
+            } else {
+                self.cursor_for_tool(ctx.window(), &data.current_tool)
+            };
+            ctx.set_cursor(&cursor);
+            data.cursor_pos = ctx.is_hot().then(|| screen_to_image(data, mouse_event.pos));
+        }
+        if let Event::MouseDown(_) = event {
+            ctx.request_focus();
+        }
+        if let Event::KeyDown(key_event) = event
+            && !key_event.mods.ctrl()
+            && let Some(tool) = tool_for_key(key_event.code)
+        {
+            select_tool(data, tool);
+        }
 
         match event {
+            Event::KeyDown(key_event) if key_event.code == Code::Space => {
+                self.space_held = true;
+            }
+            Event::KeyUp(key_event) if key_event.code == Code::Space => {
+                self.space_held = false;
+            }
+            Event::MouseDown(mouse_event)
+                if mouse_event.button == MouseButton::Middle || self.space_held && !data.is_drawing =>
+            {
+                ctx.set_active(true);
+                self.pan_anchor = Some(mouse_event.pos);
+            }
+            Event::MouseMove(mouse_event) if self.pan_anchor.is_some() => {
+                if let Some(anchor) = self.pan_anchor.replace(mouse_event.pos) {
+                    data.pan_offset += mouse_event.pos - anchor;
+                    clamp_pan(data);
+                }
+            }
+            Event::MouseUp(_) if self.pan_anchor.is_some() => {
+                ctx.set_active(false);
+                self.pan_anchor = None;
+            }
+            Event::KeyDown(key_event)
+                if key_event.mods.ctrl()
+                    && (key_event.code == Code::KeyY || key_event.mods.shift() && key_event.code == Code::KeyZ) =>
+            {
+                redo(data);
+            }
+            Event::KeyDown(key_event) if key_event.mods.ctrl() && key_event.code == Code::KeyZ => {
+                undo(data);
+            }
+            Event::KeyDown(key_event) if key_event.mods.ctrl() && key_event.code == Code::KeyF => {
+                if let Some(filter) = data.last_filter.clone() {
+                    apply_filter(data, filter);
+                    ctx.request_paint();
+                }
+            }
+            Event::KeyDown(key_event) if key_event.mods.ctrl() && key_event.code == Code::KeyC => {
+                copy_selection(data);
+            }
+            Event::KeyDown(key_event) if key_event.mods.ctrl() && key_event.code == Code::KeyX => {
+                cut_selection(data);
+                ctx.request_paint();
+            }
+            Event::KeyDown(key_event) if key_event.mods.ctrl() && key_event.code == Code::KeyV => {
+                if data.clipboard.is_some() {
+                    paste_floating(data);
+                } else {
+                    paste_from_clipboard(data);
+                }
+                ctx.request_paint();
+            }
+            Event::KeyDown(key_event) if key_event.code == Code::Enter && data.floating.is_some() => {
+                commit_floating(data);
+            }
+            Event::KeyDown(key_event) if key_event.code == Code::Escape && data.floating.is_some() => {
+                data.floating = None;
+            }
+            Event::KeyDown(key_event)
+                if key_event.code == Code::Escape && data.shape_start.is_none() && data.selection.is_some() =>
+            {
+                data.selection = None;
+            }
+            Event::KeyDown(key_event) if key_event.code == Code::KeyE && !key_event.mods.ctrl() => {
+                let tool = if data.current_tool == Tool::Eraser {
+                    data.previous_tool.clone()
+                } else {
+                    Tool::Eraser
+                };
+                select_tool(data, tool);
+            }
+            Event::KeyDown(key_event) if key_event.code == Code::KeyL && !key_event.mods.ctrl() => {
+                data.show_loupe = !data.show_loupe;
+            }
+            Event::KeyDown(key_event) if key_event.code == Code::F3 => {
+                data.show_stats = !data.show_stats;
+                ctx.request_anim_frame();
+            }
+            Event::KeyDown(key_event) if key_event.code == Code::F4 => {
+                data.show_dirty_rect = !data.show_dirty_rect;
+            }
+            Event::Wheel(mouse_event) if mouse_event.mods.ctrl() && mouse_event.wheel_delta.y != 0.0 => {
+                let zoom = if mouse_event.wheel_delta.y < 0.0 {
+                    data.zoom * ZOOM_STEP
+                } else {
+                    data.zoom / ZOOM_STEP
+                };
+                zoom_around(data, zoom, mouse_event.pos);
+                ctx.set_handled();
+            }
+            Event::AnimFrame(interval) => {
+                if *interval > 0 {
+                    data.stats.fps = 1_000_000_000.0 / *interval as f64;
+                }
+                if data.show_stats {
+                    ctx.request_anim_frame();
+                }
+                if data.is_drawing
+                    && data.current_tool == Tool::Spray
+                    && let Some(pos) = self.last_pos
+                {
+                    spray_at(data, pos, &mut self.spray_rng);
+                    ctx.request_paint();
+                    ctx.request_anim_frame();
+                }
+            }
+            Event::MouseDown(mouse_event) if data.current_tool == Tool::Polygon => {
+                add_polygon_point(data, mouse_event.pos, mouse_event.count);
+                ctx.request_paint();
+            }
+            Event::MouseDown(mouse_event) if data.current_tool == Tool::Select => {
+                ctx.set_active(true);
+                let image_pos = screen_to_image(data, mouse_event.pos);
+                if let Some(floating) = &data.floating {
+                    let floating_rect = Rect::from_origin_size(
+                        data.floating_origin,
+                        (floating.width() as f64, floating.height() as f64),
+                    );
+                    if floating_rect.contains(image_pos) {
+                        self.move_anchor = Some(image_pos - data.floating_origin);
+                        return;
+                    }
+                    commit_floating(data);
+                }
+                self.select_start = Some(image_pos);
+                data.selection_drag = Some(Rect::from_points(image_pos, image_pos));
+            }
+            Event::MouseMove(mouse_event) if ctx.is_active() && data.current_tool == Tool::Select => {
+                let image_pos = screen_to_image(data, mouse_event.pos);
+                if let Some(anchor) = self.move_anchor {
+                    data.floating_origin = image_pos - anchor;
+                } else if let Some(start) = self.select_start {
+                    let end = if mouse_event.mods.shift() { constrain_square(start, image_pos) } else { image_pos };
+                    data.selection_drag = Some(Rect::from_points(start, end));
+                }
+            }
+            Event::MouseUp(_) if data.current_tool == Tool::Select => {
+                ctx.set_active(false);
+                self.move_anchor = None;
+                if self.select_start.take().is_some()
+                    && let Some(drag) = data.selection_drag.take()
+                {
+                    let (width, height) = data.image.read().unwrap().dimensions();
+                    let rect = drag.round().intersect(Rect::new(0.0, 0.0, width as f64, height as f64));
+                    data.selection = if rect.area() > 0.0 {
+                        Some(Arc::new(Selection::rectangle(rect, width, height)))
+                    } else {
+                        None
+                    };
+                }
+            }
+            Event::Command(cmd) if cmd.is(CANCEL_SHAPE) => {
+                data.shape_start = None;
+                ctx.set_active(false);
+            }
+            Event::KeyDown(key_event) if key_event.code == Code::Escape && data.shape_start.is_some() => {
+                data.shape_start = None;
+                ctx.set_active(false);
+            }
+            Event::MouseDown(mouse_event) if data.current_tool == Tool::Picker => {
+                pick_color(data, mouse_event.pos);
+                if data.picker_switch_back {
+                    select_tool(data, data.previous_tool.clone());
+                }
+            }
+            Event::MouseDown(mouse_event)
+                if matches!(
+                    data.current_tool,
+                    Tool::Line | Tool::Rectangle | Tool::Ellipse | Tool::Crop | Tool::Gradient
+                ) =>
+            {
+                ctx.set_active(true);
+                let image_pos = screen_to_image(data, mouse_event.pos);
+                data.shape_start = Some(image_pos);
+                data.shape_end = image_pos;
+            }
+            Event::MouseMove(mouse_event) if ctx.is_active() && data.shape_start.is_some() => {
+                let image_pos = screen_to_image(data, mouse_event.pos);
+                data.shape_end = match data.shape_start {
+                    Some(start)
+                        if mouse_event.mods.shift() && matches!(data.current_tool, Tool::Rectangle | Tool::Ellipse) =>
+                    {
+                        constrain_square(start, image_pos)
+                    }
+                    _ => image_pos,
+                };
+            }
+            Event::MouseUp(_) if data.shape_start.is_some() => {
+                ctx.set_active(false);
+                if let Some(start) = data.shape_start.take() {
+                    let end = data.shape_end;
+                    match data.current_tool {
+                        Tool::Rectangle => draw_shape(data, Rect::from_points(start, end), false),
+                        Tool::Ellipse => draw_shape(data, Rect::from_points(start, end), true),
+                        Tool::Crop => crop_document(data, Rect::from_points(start, end)),
+                        Tool::Gradient => fill_gradient(data, start, end),
+                        _ => draw_line(data, start, end),
+                    }
+                }
+            }
+            Event::MouseDown(mouse_event) if data.current_tool == Tool::Fill => {
+                fill_at(data, mouse_event.pos);
+                ctx.request_paint();
+            }
+            Event::MouseDown(mouse_event) if data.current_tool == Tool::Spray => {
+                data.is_drawing = true;
+                push_undo(&mut data.history, &data.image, &data.image.read().unwrap());
+                self.last_pos = Some(mouse_event.pos);
+                spray_at(data, mouse_event.pos, &mut self.spray_rng);
+                ctx.request_anim_frame();
+            }
+            Event::MouseMove(mouse_event) if data.is_drawing && data.current_tool == Tool::Spray => {
+                self.last_pos = Some(mouse_event.pos);
+                spray_at(data, mouse_event.pos, &mut self.spray_rng);
+            }
             Event::MouseDown(mouse_event) => {
                 data.is_drawing = true;
-                draw_on_canvas(data, mouse_event.pos, ctx);
+                data.stroke_dirty = None;
+                let started = Instant::now();
+                push_undo(&mut data.history, &data.image, &data.image.read().unwrap());
+                self.checkpoint_time = started;
+                self.checkpoint_distance = 0.0;
+                self.last_pos = Some(mouse_event.pos);
+                self.last_move = started;
+                begin_stroke(data);
+                draw_on_canvas(data, &[mouse_event.pos], ctx);
+                self.stroke_time = started.elapsed();
                 if should_paint {
                     ctx.request_anim_frame();
                     self.last_paint = now;
                 }
             }
+            Event::MouseMove(mouse_event)
+                if data.is_drawing
+                    && self
+                        .last_pos
+                        .is_some_and(|last| (mouse_event.pos - last).hypot() / data.zoom < data.brush_step) => {}
             Event::MouseMove(mouse_event) if data.is_drawing => {
-                draw_on_canvas(data, mouse_event.pos, ctx);
+                let started = Instant::now();
+                let previous = self.last_pos.unwrap_or(mouse_event.pos);
+                let delta = mouse_event.pos - previous;
+                if data.rotate_to_direction && delta.hypot() >= 1.0 {
+                    data.brush_angle = delta.y.atan2(delta.x);
+                }
+                self.checkpoint_distance += delta.hypot() / data.zoom;
+                self.last_pos = Some(mouse_event.pos);
+                if data.split_long_strokes
+                    && (started.duration_since(self.checkpoint_time) >= UNDO_CHECKPOINT_INTERVAL
+                        || self.checkpoint_distance >= UNDO_CHECKPOINT_DISTANCE)
+                {
+                    commit_stroke(data);
+                    push_undo(&mut data.history, &data.image, &data.image.read().unwrap());
+                    begin_stroke(data);
+                    self.checkpoint_time = started;
+                    self.checkpoint_distance = 0.0;
+                }
+                let brush_size = data.brush_size;
+                if data.dynamic_size {
+                    let elapsed = started.duration_since(self.last_move);
+                    data.brush_size = dynamic_brush_size(brush_size, delta.hypot() / data.zoom, elapsed);
+                }
+                self.last_move = started;
+                draw_stroke_segment(data, previous, mouse_event.pos, ctx);
+                data.brush_size = brush_size;
+                self.stroke_time += started.elapsed();
                 if should_paint {
                     ctx.request_anim_frame();
                     self.last_paint = now;
@@ -79,28 +1040,68 @@ impl<W: Widget<AppState>> Controller<AppState, W> for CanvasController {
             }
             Event::MouseUp(_) => {
                 data.is_drawing = false;
+                self.last_pos = None;
+                commit_stroke(data);
+                data.stats.last_stroke_ms = self.stroke_time.as_secs_f64() * 1000.0;
             }
             _ => {}
         }
         child.event(ctx, event, data, env);
     }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &AppState,
+        env: &druid::Env,
+    ) {
+        if let LifeCycle::FocusChanged(false) = event
+            && data.shape_start.is_some()
+        {
+            ctx.submit_command(CANCEL_SHAPE.to(ctx.widget_id()));
+        }
+        child.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(
+        &mut self,
+        child: &mut W,
+        ctx: &mut UpdateCtx,
+        old_data: &AppState,
+        data: &AppState,
+        env: &druid::Env,
+    ) {
+        if old_data.current_tool != data.current_tool {
+            let cursor = self.cursor_for_tool(ctx.window(), &data.current_tool);
+            ctx.set_cursor(&cursor);
+        }
+        child.update(ctx, old_data, data, env);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum InputField {
+    BrushSize,
+    ColorR,
+    ColorG,
+    ColorB,
+    ColorA,
+    ColorHex,
+    Zoom,
+    FillTolerance,
+    FillFeather,
+    GradientEnd,
 }
 
 struct TextBoxController {
-    is_brush_size: bool,
-    is_color_r: bool,
-    is_color_g: bool,
-    is_color_b: bool,
+    field: InputField,
 }
 
 impl TextBoxController {
-    fn new(is_brush_size: bool, is_color_r: bool, is_color_g: bool, is_color_b: bool) -> Self {
-        TextBoxController {
-            is_brush_size,
-            is_color_r,
-            is_color_g,
-            is_color_b,
-        }
+    fn new(field: InputField) -> Self {
+        TextBoxController { field }
     }
 }
 
@@ -114,17 +1115,81 @@ impl<W: Widget<AppState>> Controller<AppState, W> for TextBoxController {
         env: &druid::Env,
     ) {
         match event {
-            Event::KeyDown(KeyEvent { code, .. }) if *code == Code::Enter => {
-                if self.is_brush_size {
-                    if let Ok(size) = data.brush_size_input.parse::<u32>() {
-                        if size > 0 {
-                            data.brush_size = size;
-                        }
+            Event::KeyDown(KeyEvent { code, mods, .. })
+                if matches!(code, Code::ArrowUp | Code::ArrowDown)
+                    && matches!(
+                        self.field,
+                        InputField::ColorR | InputField::ColorG | InputField::ColorB | InputField::ColorA
+                    ) =>
+            {
+                let step = if mods.shift() { 10 } else { 1 };
+                let input = match self.field {
+                    InputField::ColorR => &mut data.color_r_input,
+                    InputField::ColorG => &mut data.color_g_input,
+                    InputField::ColorB => &mut data.color_b_input,
+                    _ => &mut data.color_a_input,
+                };
+                let value = input.trim().parse::<i32>().unwrap_or(0);
+                let value = if *code == Code::ArrowUp { value + step } else { value - step };
+                *input = value.clamp(0, 255).to_string();
+                update_brush_color(data);
+                ctx.set_handled();
+                return;
+            }
+            Event::KeyDown(KeyEvent { code, .. }) if *code == Code::Enter => match self.field {
+                InputField::BrushSize => apply_brush_size_input(data),
+                InputField::ColorR | InputField::ColorG | InputField::ColorB | InputField::ColorA => {
+                    update_brush_color(data);
+                }
+                InputField::ColorHex => {
+                    if let Some(color) = parse_hex_color(&data.color_hex_input) {
+                        set_brush_color(data, color);
                     }
                 }
-                if self.is_color_r || self.is_color_g || self.is_color_b {
-                    update_brush_color(data);
+                InputField::Zoom => apply_zoom_input(data),
+                InputField::FillTolerance => {
+                    if let Ok(tolerance) = data.fill_tolerance_input.trim().parse::<u32>() {
+                        data.fill_tolerance = tolerance;
+                    }
                 }
+                InputField::FillFeather => {
+                    if let Ok(feather) = data.fill_feather_input.trim().parse::<u32>() {
+                        data.fill_feather = feather;
+                    }
+                }
+                InputField::GradientEnd => {
+                    if let Some(color) = parse_hex_color(&data.gradient_end_input) {
+                        data.gradient_end_color = color;
+                    }
+                }
+            },
+            _ => {}
+        }
+        child.event(ctx, event, data, env);
+    }
+}
+
+struct MinimapController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for MinimapController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &druid::Env,
+    ) {
+        match event {
+            Event::MouseDown(mouse_event) => {
+                ctx.set_active(true);
+                pan_to_minimap_point(data, mouse_event.pos);
+            }
+            Event::MouseMove(mouse_event) if ctx.is_active() => {
+                pan_to_minimap_point(data, mouse_event.pos);
+            }
+            Event::MouseUp(_) => {
+                ctx.set_active(false);
             }
             _ => {}
         }
@@ -132,338 +1197,4655 @@ impl<W: Widget<AppState>> Controller<AppState, W> for TextBoxController {
     }
 }
 
-fn main() {
-    let window = WindowDesc::new(build_ui())
-        .title(LocalizedString::new("Photoshop MVP"))
-        .window_size((1200.0, 800.0));
-    let mut initial_image = RgbaImage::new(800, 600);
-    for pixel in initial_image.pixels_mut() {
-        *pixel = Rgba([255, 255, 255, 255]);
-    }
-    let state = AppState {
-        image: Arc::new(RwLock::new(initial_image)),
-        brush_color: Color::BLACK,
-        is_drawing: false,
-        brush_size: 5,
-        current_tool: Tool::Brush,
-        brush_shape: BrushShape::Square,
-        brush_size_input: "5".to_string(),
-        color_r_input: "0".to_string(),
-        color_g_input: "0".to_string(),
-        color_b_input: "0".to_string(),
-        background_color: Color::WHITE,
-    };
-    AppLauncher::with_window(window)
-        .launch(state)
-        .expect("Failed to launch application");
+struct SwatchController {
+    drag_from: Option<usize>,
 }
 
-fn build_ui() -> impl Widget<AppState> {
-    let canvas = Painter::new(|ctx, state: &AppState, _env| {
-        let bounds = ctx.size().to_rect();
-        ctx.fill(bounds, &state.background_color);
+impl<W: Widget<AppState>> Controller<AppState, W> for SwatchController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &druid::Env,
+    ) {
+        match event {
+            Event::MouseDown(mouse_event) => {
+                self.drag_from = swatch_at(&data.custom_palette, mouse_event.pos);
+                if self.drag_from.is_some() {
+                    ctx.set_active(true);
+                }
+            }
+            Event::MouseMove(_) if ctx.is_active() => {
+                ctx.set_cursor(&Cursor::Pointer);
+            }
+            Event::MouseUp(mouse_event) if ctx.is_active() => {
+                ctx.set_active(false);
+                ctx.clear_cursor();
+                let Some(from) = self.drag_from.take() else {
+                    return;
+                };
+                match swatch_at(&data.custom_palette, mouse_event.pos) {
+                    Some(to) if to != from => {
+                        let palette = Arc::make_mut(&mut data.custom_palette);
+                        let color = palette.remove(from);
+                        palette.insert(to, color);
+                        save_custom_palette(data);
+                    }
+                    Some(_) => set_brush_color(data, data.custom_palette[from]),
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+        child.event(ctx, event, data, env);
+    }
+}
 
-        let image = state.image.read().unwrap();
-        let image_data = image.as_raw();
-        let piet_image = ctx
-            .make_image(
-                image.width() as usize,
-                image.height() as usize,
-                image_data,
-                ImageFormat::RgbaPremul,
-            )
-            .unwrap();
-        ctx.draw_image(
-            &piet_image,
-            bounds,
-            druid::piet::InterpolationMode::Bilinear,
-        );
-    })
-    .fix_size(800.0, 600.0)
-    .controller(CanvasController::new());
+struct PaletteSwatchController;
 
-    let toolbar = Flex::column()
-        .with_child(Label::new("Tools").with_text_size(18.0))
-        .with_spacer(10.0)
-        .with_child(
-            Button::new("Brush")
-                .on_click(|_ctx, state: &mut AppState, _env| {
-                    state.current_tool = Tool::Brush;
-                })
-        )
-        .with_child(
-            Button::new("Eraser")
-                .on_click(|_ctx, state: &mut AppState, _env| {
-                    state.current_tool = Tool::Eraser;
-                })
-        )
-        .with_spacer(10.0)
-        .with_child(Label::new("Brush Shape").with_text_size(16.0))
-        .with_child(
-            Button::new("Square")
-                .on_click(|_ctx, state: &mut AppState, _env| {
-                    state.brush_shape = BrushShape::Square;
-                })
-        )
-        .with_child(
-            Button::new("Circle")
-                .on_click(|_ctx, state: &mut AppState, _env| {
-                    state.brush_shape = BrushShape::Circle;
-                })
+impl<W: Widget<AppState>> Controller<AppState, W> for PaletteSwatchController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &druid::Env,
+    ) {
+        if let Event::MouseDown(mouse_event) = event
+            && let Some(index) = swatch_at(&data.palette, mouse_event.pos)
+        {
+            let (color, name) = data.palette[index].clone();
+            set_brush_color(data, color);
+            if !name.is_empty() {
+                data.status_message = StatusMessage::new("status-picked").with_arg("name", name);
+            }
+        }
+        child.event(ctx, event, data, env);
+    }
+}
+
+struct RecentSwatchController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for RecentSwatchController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &druid::Env,
+    ) {
+        if let Event::MouseDown(mouse_event) = event
+            && let Some(index) = swatch_at(&data.recent_colors, mouse_event.pos)
+        {
+            set_brush_color(data, data.recent_colors[index]);
+        }
+        child.event(ctx, event, data, env);
+    }
+}
+
+struct Delegate;
+
+impl AppDelegate<AppState> for Delegate {
+    fn command(
+        &mut self,
+        ctx: &mut DelegateCtx,
+        _target: Target,
+        cmd: &Command,
+        data: &mut AppState,
+        _env: &Env,
+    ) -> Handled {
+        if cmd.is(druid::commands::CLOSE_WINDOW) && data.dirty {
+            data.exit_pending = true;
+            return Handled::Yes;
+        }
+        if let Some(file_info) = cmd.get(LOAD_STAMP) {
+            match open_image(file_info.path()) {
+                Ok((stamp, warning)) => {
+                    data.brush_stamp = Some(Arc::new(stamp));
+                    data.brush_shape = BrushShape::Stamp;
+                    data.status_message = match warning {
+                        Some(err) => StatusMessage::new("status-stamp-damaged").with_arg("error", err),
+                        None => StatusMessage::new("status-stamp-loaded"),
+                    };
+                }
+                Err(err) => data.status_message = StatusMessage::new("status-stamp-load-failed").with_arg("error", err),
+            }
+            return Handled::Yes;
+        }
+        if let Some(file_info) = cmd.get(SAVE_IMAGE) {
+            let exit_after_save = std::mem::take(&mut data.exit_after_save);
+            data.status_message = match save_document(data, file_info.path()) {
+                Ok(()) if exit_after_save => std::process::exit(0),
+                Ok(()) => StatusMessage::new("status-image-saved").with_arg("path", file_info.path().display()),
+                Err(err) => {
+                    data.exit_pending = exit_after_save;
+                    StatusMessage::new("status-image-save-failed").with_arg("error", err)
+                }
+            };
+            return Handled::Yes;
+        }
+        if cmd.is(druid::commands::SAVE_PANEL_CANCELLED) && data.exit_after_save {
+            data.exit_after_save = false;
+            data.exit_pending = true;
+            return Handled::Yes;
+        }
+        if let Some(file_info) = cmd.get(EXPORT_SELECTION) {
+            let Some(region) = selection_region(data) else {
+                data.status_message = StatusMessage::new("status-nothing-selected");
+                return Handled::Yes;
+            };
+            data.status_message = match save_image(&region, file_info.path(), data.jpeg_quality.round() as u8) {
+                Ok(()) => StatusMessage::new("status-selection-exported").with_arg("path", file_info.path().display()),
+                Err(err) => StatusMessage::new("status-selection-export-failed").with_arg("error", err),
+            };
+            return Handled::Yes;
+        }
+        if let Some(file_info) = cmd.get(EXPORT_TILES) {
+            data.status_message = match parse_tile_grid(data) {
+                Ok(grid) => match export_tiles(&composite_layers(&data.layers), file_info.path(), grid) {
+                    Ok(0) => StatusMessage::new("status-tiles-none"),
+                    Ok(count) => StatusMessage::new("status-tiles-exported")
+                        .with_arg("count", count)
+                        .with_arg("path", file_info.path().display()),
+                    Err(err) => StatusMessage::new("status-tiles-export-failed").with_arg("error", err),
+                },
+                Err(err) => err,
+            };
+            return Handled::Yes;
+        }
+        if let Some(file_info) = cmd.get(EXPORT_VIEW) {
+            data.status_message = match render_view(data).save(file_info.path()) {
+                Ok(()) => StatusMessage::new("status-view-exported").with_arg("path", file_info.path().display()),
+                Err(err) => StatusMessage::new("status-view-export-failed").with_arg("error", err),
+            };
+            return Handled::Yes;
+        }
+        if let Some(file_info) = cmd.get(LOAD_PALETTE) {
+            data.status_message = match load_gpl(file_info.path()) {
+                Ok(colors) if colors.is_empty() => StatusMessage::new("status-palette-empty"),
+                Ok(mut colors) => {
+                    let skipped = colors.len().saturating_sub(MAX_PALETTE_SWATCHES);
+                    colors.truncate(MAX_PALETTE_SWATCHES);
+                    let count = colors.len();
+                    data.palette = Arc::new(colors);
+                    if skipped > 0 {
+                        StatusMessage::new("status-palette-loaded-partial")
+                            .with_arg("count", count)
+                            .with_arg("skipped", skipped)
+                    } else {
+                        StatusMessage::new("status-palette-loaded").with_arg("count", count)
+                    }
+                }
+                Err(err) => StatusMessage::new("status-palette-load-failed").with_arg("error", err),
+            };
+            return Handled::Yes;
+        }
+        if let Some(file_info) = cmd.get(SAVE_PALETTE) {
+            let colors: Vec<(Color, String)> = data
+                .recent_colors
+                .iter()
+                .map(|color| (*color, format_hex_color(*color)))
+                .collect();
+            data.status_message = match save_gpl(file_info.path(), "Recent Colors", &colors) {
+                Ok(()) => StatusMessage::new("status-palette-saved").with_arg("path", file_info.path().display()),
+                Err(err) => StatusMessage::new("status-palette-save-failed").with_arg("error", err),
+            };
+            return Handled::Yes;
+        }
+        if let Some(file_info) = cmd.get(EXPORT_CHANNEL) {
+            let channel = extract_channel(&composite_layers(&data.layers), data.export_channel);
+            data.status_message = match channel.save(file_info.path()) {
+                Ok(()) => StatusMessage::new("status-channel-exported").with_arg("path", file_info.path().display()),
+                Err(err) => StatusMessage::new("status-channel-export-failed").with_arg("error", err),
+            };
+            return Handled::Yes;
+        }
+        if let Some(result) = cmd.get(URL_IMAGE_LOADED) {
+            match result {
+                Ok(image) => {
+                    replace_document(data, image.clone());
+                    data.status_message = StatusMessage::new("status-url-opened");
+                }
+                Err(err) => data.status_message = StatusMessage::new("status-url-open-failed").with_arg("error", err),
+            }
+            return Handled::Yes;
+        }
+        if let Some(file_info) = cmd.get(OPEN_IMAGE) {
+            let path = file_info.path().to_path_buf();
+            data.status_message = StatusMessage::new("status-image-opening").with_arg("path", path.display());
+            let sink = ctx.get_external_handle();
+            thread::spawn(move || {
+                let result = open_image(&path);
+                let _ = sink.submit_command(IMAGE_OPENED, (path, result), Target::Auto);
+            });
+            return Handled::Yes;
+        }
+        if let Some((path, result)) = cmd.get(IMAGE_OPENED) {
+            match result {
+                Ok((image, warning)) => {
+                    replace_document(data, image.clone());
+                    data.status_message = match warning {
+                        Some(err) => StatusMessage::new("status-image-damaged").with_arg("error", err),
+                        None => StatusMessage::new("status-image-opened").with_arg("path", path.display()),
+                    };
+                }
+                Err(err) => {
+                    data.status_message = StatusMessage::new("status-image-open-failed").with_arg("error", err)
+                }
+            }
+            return Handled::Yes;
+        }
+        Handled::No
+    }
+}
+
+fn main() {
+    let initial_image = create_blank_image(800, 600, Color::WHITE);
+    let state = AppState::new(Arc::new(RwLock::new(initial_image)), Settings::load());
+    let window = WindowDesc::new(build_ui(Size::new(state.canvas_width, state.canvas_height)))
+        .title(LocalizedString::new("app-title"))
+        .window_size((1200.0, 800.0));
+    AppLauncher::with_window(window)
+        .localization_resources(
+            vec!["builtin.ftl".to_string(), "photoshop-mvp.ftl".to_string()],
+            "resources/i18n".to_string(),
         )
+        .delegate(Delegate)
+        .launch(state)
+        .expect("Failed to launch application");
+}
+
+fn build_ui(canvas_size: Size) -> impl Widget<AppState> {
+    let image_cache: RefCell<Option<CachedImage>> = RefCell::new(None);
+    let canvas = Painter::new(move |ctx, state: &AppState, _env| {
+        let bounds = ctx.size().to_rect();
+        ctx.fill(bounds, &APP_BACKDROP);
+        ctx.clip(bounds);
+        let image = state.image.read().unwrap();
+        draw_checkerboard(ctx, image_rect(state, &image).intersect(bounds));
+
+        let mut cache = image_cache.borrow_mut();
+        let key = canvas_cache_key(state);
+        if !cache.as_ref().is_some_and(|(cached_key, _)| canvas_cache_is_current(cached_key, &key)) {
+            let composite = match &key.2 {
+                Some((index, params)) => composite_filter_preview(state, &image, *index, params),
+                None => composite_layers(&state.layers),
+            };
+            let piet_image = ctx
+                .make_image(
+                    composite.width() as usize,
+                    composite.height() as usize,
+                    composite.as_raw(),
+                    ImageFormat::RgbaSeparate,
+                )
+                .unwrap();
+            *cache = Some((key, piet_image));
+        }
+        let (_, piet_image) = cache.as_ref().unwrap();
+        ctx.draw_image(
+            piet_image,
+            image_rect(state, &image),
+            druid::piet::InterpolationMode::Bilinear,
+        );
+        // Only the part of the stack the stroke has touched is recomposited and uploaded.
+        if let Some(stroke) = &state.stroke
+            && let Some(dirty) = state.stroke_dirty
+        {
+            let stroke = stroke.lock().unwrap();
+            let (width, height) = stroke.layer.dimensions();
+            let region = dirty.intersect(Rect::new(0.0, 0.0, width as f64, height as f64));
+            if region.area() > 0.0 {
+                let (x, y) = (region.x0 as u32, region.y0 as u32);
+                let (region_width, region_height) = (region.x1 as u32 - x, region.y1 as u32 - y);
+                let preview = composite_region(
+                    &state.layers,
+                    (x, y, region_width, region_height),
+                    Some((state.active_layer, &stroke.layer)),
+                );
+                let stroke_image = ctx
+                    .make_image(
+                        region_width as usize,
+                        region_height as usize,
+                        preview.as_raw(),
+                        ImageFormat::RgbaSeparate,
+                    )
+                    .unwrap();
+                ctx.draw_image(
+                    &stroke_image,
+                    Rect::from_points(
+                        image_to_screen(state, region.origin()),
+                        image_to_screen(state, Point::new(region.x1, region.y1)),
+                    ),
+                    druid::piet::InterpolationMode::Bilinear,
+                );
+            }
+        }
+
+        if state.current_tool == Tool::Polygon && !state.polygon_points.is_empty() {
+            let mut outline = BezPath::new();
+            for (index, point) in state.polygon_points.iter().enumerate() {
+                let screen_point = image_to_screen(state, *point);
+                if index == 0 {
+                    outline.move_to(screen_point);
+                } else {
+                    outline.line_to(screen_point);
+                }
+            }
+            ctx.stroke(outline, &state.brush_color, 1.0);
+        }
+
+        if let Some(start) = state.shape_start
+            && state.current_tool == Tool::Crop
+        {
+            draw_dashed_rect(ctx, state, Rect::from_points(start, state.shape_end));
+        } else if let Some(start) = state.shape_start {
+            let start = image_to_screen(state, start);
+            let end = image_to_screen(state, state.shape_end);
+            let thickness = stroke_thickness(state) * state.zoom;
+            let bounds = Rect::from_points(start, end);
+            let outline = bounds.inset(-thickness / 2.0);
+            match state.current_tool {
+                Tool::Rectangle if state.shape_fill => ctx.fill(bounds, &state.brush_color),
+                Tool::Rectangle => ctx.stroke(outline, &state.brush_color, thickness),
+                Tool::Ellipse if state.shape_fill => ctx.fill(Ellipse::from_rect(bounds), &state.brush_color),
+                Tool::Ellipse => ctx.stroke(Ellipse::from_rect(outline), &state.brush_color, thickness),
+                Tool::Gradient => ctx.stroke(Line::new(start, end), &state.brush_color, 1.0),
+                _ => ctx.stroke(Line::new(start, end), &state.brush_color, thickness),
+            }
+        }
+        if let Some(floating) = &state.floating {
+            let floating_image = ctx
+                .make_image(
+                    floating.width() as usize,
+                    floating.height() as usize,
+                    floating.as_raw(),
+                    ImageFormat::RgbaSeparate,
+                )
+                .unwrap();
+            let floating_rect = Rect::from_origin_size(
+                state.floating_origin,
+                (floating.width() as f64, floating.height() as f64),
+            );
+            let screen_rect = Rect::from_points(
+                image_to_screen(state, floating_rect.origin()),
+                image_to_screen(state, Point::new(floating_rect.x1, floating_rect.y1)),
+            );
+            ctx.draw_image(&floating_image, screen_rect, druid::piet::InterpolationMode::NearestNeighbor);
+            draw_dashed_rect(ctx, state, floating_rect);
+        }
+        if let Some(bounds) = state.selection.as_ref().and_then(|selection| selection.bounds) {
+            draw_dashed_rect(ctx, state, bounds);
+        }
+        if let Some(drag) = state.selection_drag {
+            draw_dashed_rect(ctx, state, drag);
+        }
+
+        if state.show_dirty_rect
+            && let Some(dirty) = state.stroke_dirty
+        {
+            let screen_rect = Rect::from_points(
+                image_to_screen(state, dirty.origin()),
+                image_to_screen(state, Point::new(dirty.x1, dirty.y1)),
+            );
+            ctx.stroke(screen_rect.inset(-0.5), &Color::rgb8(255, 0, 0), 1.0);
+        }
+
+        if state.show_loupe
+            && let Some(cursor) = state.cursor_pos
+        {
+            draw_loupe(ctx, state, &image, cursor);
+        }
+
+        if state.show_stats {
+            let image_bytes = image.width() as f64 * image.height() as f64 * 4.0;
+            let history_bytes: f64 = state
+                .history
+                .undo
+                .iter()
+                .chain(&state.history.redo)
+                .map(|snapshot| snapshot.size_bytes() as f64)
+                .sum();
+            let text = format!(
+                "FPS: {:.0}\nLast stroke: {:.1} ms\nImage memory: {:.1} MB\nUndo: {} steps, {:.1} MB",
+                state.stats.fps,
+                state.stats.last_stroke_ms,
+                image_bytes / (1024.0 * 1024.0),
+                state.history.undo.len(),
+                history_bytes / (1024.0 * 1024.0),
+            );
+            let layout = ctx
+                .text()
+                .new_text_layout(text)
+                .text_color(Color::WHITE)
+                .build()
+                .unwrap();
+            let panel = Rect::from_origin_size((8.0, 8.0), layout.size() + Size::new(12.0, 8.0));
+            ctx.fill(panel, &Color::rgba8(0, 0, 0, 160));
+            ctx.draw_text(&layout, (14.0, 12.0));
+        }
+    })
+    .fix_size(canvas_size.width, canvas_size.height)
+    .controller(CanvasController::new());
+
+    let minimap_cache: RefCell<Option<CachedMinimap>> = RefCell::new(None);
+    let minimap = Painter::new(move |ctx, state: &AppState, _env| {
+        let bounds = ctx.size().to_rect();
+        ctx.fill(bounds, &APP_BACKDROP);
+
+        let image = state.image.read().unwrap();
+        let mut cache = minimap_cache.borrow_mut();
+        let cached = cache.as_ref().is_some_and(|(source, generation, _)| {
+            Arc::ptr_eq(source, &state.layers) && *generation == state.image_generation
+        });
+        if !cached {
+            let composite = composite_layers(&state.layers);
+            let piet_image = ctx
+                .make_image(
+                    composite.width() as usize,
+                    composite.height() as usize,
+                    composite.as_raw(),
+                    ImageFormat::RgbaSeparate,
+                )
+                .unwrap();
+            *cache = Some((state.layers.clone(), state.image_generation, piet_image));
+        }
+        let (_, _, piet_image) = cache.as_ref().unwrap();
+        ctx.draw_image(
+            piet_image,
+            bounds,
+            druid::piet::InterpolationMode::Bilinear,
+        );
+        ctx.stroke(minimap_viewport(state, &image), &Color::rgb8(255, 0, 0), 1.0);
+    })
+    .fix_size(MINIMAP_WIDTH, MINIMAP_HEIGHT)
+    .controller(MinimapController);
+
+    let navigator = Flex::column()
+        .with_child(Label::new(LocalizedString::new("navigator-title")).with_text_size(18.0))
         .with_spacer(10.0)
-        .with_child(Label::new("Brush Size").with_text_size(16.0))
+        .with_child(minimap)
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("zoom-label")).with_text_size(16.0))
         .with_child(
             TextBox::new()
-                .with_placeholder("Enter size (px)")
-                .lens(AppState::brush_size_input)
-                .controller(TextBoxController::new(true, false, false, false))
+                .with_placeholder(LocalizedString::new("zoom-placeholder"))
+                .lens(AppState::zoom_input)
+                .controller(TextBoxController::new(InputField::Zoom))
         )
-        .with_spacer(10.0)
-        .with_child(Label::new("Brush Color").with_text_size(16.0))
         .with_child(
             Flex::row()
                 .with_child(
-                    TextBox::new()
-                        .with_placeholder("R (0-255)")
-                        .lens(AppState::color_r_input)
-                        .controller(TextBoxController::new(false, true, false, false))
-                        .fix_width(60.0)
-                )
-                .with_child(
-                    TextBox::new()
-                        .with_placeholder("G (0-255)")
-                        .lens(AppState::color_g_input)
-                        .controller(TextBoxController::new(false, false, true, false))
-                        .fix_width(60.0)
-                )
-                .with_child(
-                    TextBox::new()
-                        .with_placeholder("B (0-255)")
-                        .lens(AppState::color_b_input)
-                        .controller(TextBoxController::new(false, false, false, true))
-                        .fix_width(60.0)
-                )
-        )
-        .with_spacer(10.0)
-        .with_child(Label::new("Color Palette").with_text_size(16.0))
-        .with_child(
-            Flex::column()
-                .with_child(
-                    Button::new("Red")
+                    Button::new(LocalizedString::new("zoom-out"))
                         .on_click(|_ctx, state: &mut AppState, _env| {
-                            state.brush_color = Color::rgb8(255, 0, 0);
-                            state.color_r_input = "255".to_string();
-                            state.color_g_input = "0".to_string();
-                            state.color_b_input = "0".to_string();
+                            set_zoom(state, state.zoom / ZOOM_STEP);
                         })
                 )
                 .with_child(
-                    Button::new("Green")
+                    Button::new(LocalizedString::new("zoom-in"))
                         .on_click(|_ctx, state: &mut AppState, _env| {
-                            state.brush_color = Color::rgb8(0, 255, 0);
-                            state.color_r_input = "0".to_string();
-                            state.color_g_input = "255".to_string();
-                            state.color_b_input = "0".to_string();
+                            set_zoom(state, state.zoom * ZOOM_STEP);
                         })
                 )
-                .with_child(Button::new("Blue")
-                        .on_click(|_ctx, state: &mut AppState, _env| {
-                            state.brush_color = Color::rgb8(0, 0, 255);
-                            state.color_r_input = "0".to_string();
-                            state.color_g_input = "0".to_string();
-                            state.color_b_input = "255".to_string();
-                        })
-                )
-                .with_child(
-                    Button::new("Cyan")
-                        .on_click(|_ctx, state: &mut AppState, _env| {
-                            state.brush_color = Color::rgb8(0, 255, 255);
-                            state.color_r_input = "0".to_string();
-                            state.color_g_input = "255".to_string();
-                            state.color_b_input = "255".to_string();
-                        })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("zoom-fit"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    fit_to_view(state);
+                })
+        )
+        .padding(10.0)
+        .fix_width(200.0);
+
+    let preview_cache: RefCell<Option<CachedBrushPreview>> = RefCell::new(None);
+    let brush_preview = Painter::new(move |ctx, state: &AppState, _env| {
+        let bounds = ctx.size().to_rect();
+        draw_checkerboard(ctx, bounds);
+
+        let key = brush_preview_key(state);
+        let mut cache = preview_cache.borrow_mut();
+        if !cache.as_ref().is_some_and(|(cached_key, _)| cached_key.same(&key)) {
+            let preview = brush_preview_image(state);
+            let piet_image = ctx
+                .make_image(
+                    preview.width() as usize,
+                    preview.height() as usize,
+                    preview.as_raw(),
+                    ImageFormat::RgbaSeparate,
                 )
+                .unwrap();
+            *cache = Some((key, piet_image));
+        }
+        let (key, piet_image) = cache.as_ref().unwrap();
+        let size = ((key.radius * 2 + 1) as f64).min(bounds.width());
+        ctx.draw_image(
+            piet_image,
+            Rect::from_center_size(bounds.center(), (size, size)),
+            druid::piet::InterpolationMode::Bilinear,
+        );
+    })
+    .fix_size(64.0, 64.0);
+
+    let custom_swatches = Painter::new(|ctx, state: &AppState, _env| {
+        for (index, color) in state.custom_palette.iter().enumerate() {
+            let rect = swatch_rect(index).inset(-1.0);
+            ctx.fill(rect, color);
+            ctx.stroke(rect, &Color::grey8(96), 1.0);
+        }
+    })
+    .fix_size(
+        SWATCH_SIZE * SWATCHES_PER_ROW as f64,
+        SWATCH_SIZE * MAX_CUSTOM_SWATCHES.div_ceil(SWATCHES_PER_ROW) as f64,
+    )
+    .controller(SwatchController { drag_from: None });
+
+    let palette_swatches = Painter::new(|ctx, state: &AppState, _env| {
+        for (index, (color, _)) in state.palette.iter().enumerate() {
+            let rect = swatch_rect(index).inset(-1.0);
+            ctx.fill(rect, color);
+            ctx.stroke(rect, &Color::grey8(96), 1.0);
+        }
+    })
+    .fix_size(
+        SWATCH_SIZE * SWATCHES_PER_ROW as f64,
+        SWATCH_SIZE * MAX_PALETTE_SWATCHES.div_ceil(SWATCHES_PER_ROW) as f64,
+    )
+    .controller(PaletteSwatchController);
+
+    let recent_swatches = Painter::new(|ctx, state: &AppState, _env| {
+        for (index, color) in state.recent_colors.iter().enumerate() {
+            let rect = swatch_rect(index).inset(-1.0);
+            ctx.fill(rect, color);
+            ctx.stroke(rect, &Color::grey8(96), 1.0);
+        }
+    })
+    .fix_size(SWATCH_SIZE * SWATCHES_PER_ROW as f64, SWATCH_SIZE)
+    .controller(RecentSwatchController);
+
+    let mut filter_buttons = Flex::column();
+    for (index, filter) in filters::registry(&FilterParams::default()).iter().enumerate() {
+        filter_buttons.add_child(
+            Flex::row()
                 .with_child(
-                    Button::new("Brown")
-                        .on_click(|_ctx, state: &mut AppState, _env| {
-                            state.brush_color = Color::rgb8(139,69,19);
-                            state.color_r_input = "139".to_string();
-                            state.color_g_input = "69".to_string();
-                            state.color_b_input = "19".to_string();
+                    Button::new(LocalizedString::new(filter.name()))
+                        .on_click(move |ctx, state: &mut AppState, _env| {
+                            let filter = filters::registry(&filter_params(state)).swap_remove(index);
+                            state.filter_preview = None;
+                            apply_filter(state, filter);
+                            ctx.request_paint();
                         })
                 )
                 .with_child(
-                    Button::new("Yellow")
-                        .on_click(|_ctx, state: &mut AppState, _env| {
-                            state.brush_color = Color::rgb8(255, 255, 0);
-                            state.color_r_input = "255".to_string();
-                            state.color_g_input = "255".to_string();
-                            state.color_b_input = "0".to_string();
-                        })
+                    Checkbox::new(LocalizedString::new("filter-preview")).lens(lens::Map::new(
+                        move |state: &AppState| state.filter_preview == Some(index),
+                        move |state: &mut AppState, preview: bool| {
+                            if preview {
+                                state.filter_preview = Some(index);
+                            } else if state.filter_preview == Some(index) {
+                                state.filter_preview = None;
+                            }
+                        },
+                    ))
                 )
+        );
+    }
+
+    let toolbar = Flex::column()
+        .with_child(Label::new(LocalizedString::new("tools-title")).with_text_size(18.0))
+        .with_spacer(10.0)
+        .with_child(
+            Flex::row()
                 .with_child(
-                    Button::new("test color")
+                    Button::new(LocalizedString::new("edit-undo"))
                         .on_click(|_ctx, state: &mut AppState, _env| {
-                            state.brush_color = Color::rgb8(196, 55, 140);
-                            state.color_r_input = "196".to_string();
-                            state.color_g_input = "55".to_string();
-                            state.color_b_input = "140".to_string();
+                            undo(state);
                         })
+                        .disabled_if(|state: &AppState, _env| state.history.undo.is_empty())
                 )
                 .with_child(
-                    Button::new("test color1")
+                    Button::new(LocalizedString::new("edit-redo"))
                         .on_click(|_ctx, state: &mut AppState, _env| {
-                            state.brush_color = Color::rgb8(72,61,139);
-                            state.color_r_input = "72".to_string();
-                            state.color_g_input = "61".to_string();
-                            state.color_b_input = "139".to_string();
+                            redo(state);
                         })
+                        .disabled_if(|state: &AppState, _env| state.history.redo.is_empty())
                 )
-                .with_child(
-                    Button::new("Black")
-                        .on_click(|_ctx, state: &mut AppState, _env| {
-                            state.brush_color = Color::rgb8(0, 0, 0);
-                            state.color_r_input = "0".to_string();
-                            state.color_g_input = "0".to_string();
-                            state.color_b_input = "0".to_string();
-                        })
-                ))
-        .with_spacer(10.0)
-        .with_child(Label::new("Background Color").with_text_size(16.0))
+        )
         .with_child(
-            Button::new("White")
+            Button::new(LocalizedString::new("tool-brush"))
                 .on_click(|_ctx, state: &mut AppState, _env| {
-                    set_background_color(state, Color::WHITE);
+                    select_tool(state, Tool::Brush);
                 })
         )
         .with_child(
-            Button::new("Gray")
+            Button::new(LocalizedString::new("tool-eraser"))
                 .on_click(|_ctx, state: &mut AppState, _env| {
-                    set_background_color(state, Color::rgb8(128, 128, 128));
+                    select_tool(state, Tool::Eraser);
                 })
         )
         .with_child(
-            Button::new("Transparent")
+            RadioGroup::column(vec![
+                (LocalizedString::new("erase-mode-transparent"), EraseMode::Transparent),
+                (LocalizedString::new("erase-mode-background"), EraseMode::Background),
+                (LocalizedString::new("erase-mode-secondary"), EraseMode::Secondary),
+            ])
+            .lens(AppState::erase_mode)
+        )
+        .with_child(
+            Button::new(LocalizedString::new("tool-polygon"))
                 .on_click(|_ctx, state: &mut AppState, _env| {
-                    set_background_color(state, Color::rgba8(0, 0, 0, 0));
+                    select_tool(state, Tool::Polygon);
+                    state.polygon_points = Arc::new(Vec::new());
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("tool-select"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    select_tool(state, Tool::Select);
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("tool-line"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    select_tool(state, Tool::Line);
                 })
         )
+        .with_child(
+            Button::new(LocalizedString::new("tool-rectangle"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    select_tool(state, Tool::Rectangle);
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("tool-ellipse"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    select_tool(state, Tool::Ellipse);
+                })
+        )
+        .with_child(Checkbox::new(LocalizedString::new("shape-fill")).lens(AppState::shape_fill))
+        .with_child(
+            Button::new(LocalizedString::new("tool-fill"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    select_tool(state, Tool::Fill);
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("tool-crop"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    select_tool(state, Tool::Crop);
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("tool-spray"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    select_tool(state, Tool::Spray);
+                })
+        )
+        .with_child(Label::new(
+            LocalizedString::new("spray-density")
+                .with_arg("count", |state: &AppState, _env| (state.spray_density.round() as u32).into()),
+        ))
+        .with_child(Slider::new().with_range(1.0, 200.0).lens(AppState::spray_density))
+        .with_child(
+            Button::new(LocalizedString::new("tool-pencil"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    select_tool(state, Tool::Pencil);
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("tool-gradient"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    select_tool(state, Tool::Gradient);
+                })
+        )
+        .with_child(
+            RadioGroup::row(vec![
+                (LocalizedString::new("gradient-linear"), GradientMode::Linear),
+                (LocalizedString::new("gradient-radial"), GradientMode::Radial),
+            ])
+            .lens(AppState::gradient_mode)
+        )
+        .with_child(
+            TextBox::new()
+                .with_placeholder(LocalizedString::new("gradient-end-placeholder"))
+                .lens(AppState::gradient_end_input)
+                .controller(TextBoxController::new(InputField::GradientEnd))
+                .fix_width(180.0)
+        )
+        .with_child(
+            Button::new(LocalizedString::new("tool-smudge"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    select_tool(state, Tool::Smudge);
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("tool-picker"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    select_tool(state, Tool::Picker);
+                })
+        )
+        .with_child(Either::new(
+            |state: &AppState, _env| state.current_tool == Tool::Picker,
+            Flex::column()
+                .with_child(Label::new(LocalizedString::new("picker-sample-size")))
+                .with_child(
+                    RadioGroup::row(vec![
+                        (LocalizedString::new("picker-sample-1"), 1),
+                        (LocalizedString::new("picker-sample-3"), 3),
+                        (LocalizedString::new("picker-sample-5"), 5),
+                    ])
+                    .lens(AppState::picker_sample_size)
+                )
+                .with_child(Checkbox::new(LocalizedString::new("picker-switch-back")).lens(AppState::picker_switch_back))
+                .with_child(Checkbox::new(LocalizedString::new("picker-merged")).lens(AppState::picker_merged)),
+            Flex::column(),
+        ))
+        .with_child(Label::new(LocalizedString::new("fill-tolerance")))
+        .with_child(
+            TextBox::new()
+                .with_placeholder(LocalizedString::new("fill-tolerance-placeholder"))
+                .lens(AppState::fill_tolerance_input)
+                .controller(TextBoxController::new(InputField::FillTolerance))
+        )
+        .with_child(Label::new(LocalizedString::new("fill-feather")))
+        .with_child(
+            TextBox::new()
+                .with_placeholder(LocalizedString::new("fill-feather-placeholder"))
+                .lens(AppState::fill_feather_input)
+                .controller(TextBoxController::new(InputField::FillFeather))
+        )
         .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("brush-shape-title")).with_text_size(16.0))
         .with_child(
-            Button::new("Save Image")
+            Button::new(LocalizedString::new("brush-shape-square"))
                 .on_click(|_ctx, state: &mut AppState, _env| {
-                    let image = state.image.read().unwrap();
-                    image.save("output.png").expect("Failed to save image");
+                    state.brush_shape = BrushShape::Square;
                 })
         )
         .with_child(
-            Button::new("Clear Canvas")
+            Button::new(LocalizedString::new("brush-shape-circle"))
                 .on_click(|_ctx, state: &mut AppState, _env| {
-                    let mut image = state.image.write().unwrap();
-                    for pixel in image.pixels_mut() {
-                        *pixel = Rgba([255, 255, 255, 255]);
-                    }
+                    state.brush_shape = BrushShape::Circle;
                 })
         )
         .with_child(
-            Button::new("EXIT")
-                .on_click(|_ctx, _state: &mut AppState, _env| {
-                    std::process::exit(0);
+            Button::new(LocalizedString::new("brush-load-stamp"))
+                .on_click(|ctx, _state: &mut AppState, _env| {
+                    let options = FileDialogOptions::new()
+                        .allowed_types(vec![FileSpec::PNG, FileSpec::JPG, FileSpec::GIF])
+                        .accept_command(LOAD_STAMP);
+                    ctx.submit_command(druid::commands::SHOW_OPEN_PANEL.with(options));
                 })
         )
-        .padding(10.0)
-        .fix_width(200.0);
-
-    Flex::row()
-        .with_child(toolbar)
-        .with_flex_spacer(1.0)
-        .with_child(Align::centered(canvas))
-        .with_flex_spacer(1.0)
-        .padding(10.0)
-}
+        .with_child(Checkbox::new(LocalizedString::new("brush-rotate-to-direction")).lens(AppState::rotate_to_direction))
+        .with_child(Checkbox::new(LocalizedString::new("brush-dynamic-size")).lens(AppState::dynamic_size))
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("stamp-scaling-title")).with_text_size(16.0))
+        .with_child(
+            Button::new(LocalizedString::new("stamp-scaling-nearest"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    state.stamp_interp = StampInterp::Nearest;
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("stamp-scaling-bilinear"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    state.stamp_interp = StampInterp::Bilinear;
+                })
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("falloff-title")).with_text_size(16.0))
+        .with_child(
+            RadioGroup::column(vec![
+                (LocalizedString::new("falloff-linear"), FalloffCurve::Linear),
+                (LocalizedString::new("falloff-ease-in"), FalloffCurve::EaseIn),
+                (LocalizedString::new("falloff-ease-out"), FalloffCurve::EaseOut),
+            ])
+            .lens(AppState::falloff_curve)
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("symmetry-title")).with_text_size(16.0))
+        .with_child(
+            RadioGroup::column(vec![
+                (LocalizedString::new("symmetry-none"), Symmetry::None),
+                (LocalizedString::new("symmetry-vertical"), Symmetry::Vertical),
+                (LocalizedString::new("symmetry-horizontal"), Symmetry::Horizontal),
+                (LocalizedString::new("symmetry-both"), Symmetry::Both),
+            ])
+            .lens(AppState::symmetry)
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("brush-size-title")).with_text_size(16.0))
+        .with_child(
+            TextBox::new()
+                .with_placeholder(LocalizedString::new("brush-size-placeholder"))
+                .lens(AppState::brush_size_input)
+                .controller(TextBoxController::new(InputField::BrushSize))
+        )
+        .with_child(Checkbox::new(LocalizedString::new("brush-antialiasing")).lens(AppState::brush_antialiasing))
+        .with_child(Checkbox::new(LocalizedString::new("undo-split-strokes")).lens(AppState::split_long_strokes))
+        .with_child(Label::new(
+            LocalizedString::new("brush-opacity")
+                .with_arg("percent", |state: &AppState, _env| {
+                    ((state.brush_opacity * 100.0).round() as u32).into()
+                }),
+        ))
+        .with_child(Slider::new().lens(AppState::brush_opacity))
+        .with_child(Label::new(
+            LocalizedString::new("brush-flow")
+                .with_arg("percent", |state: &AppState, _env| {
+                    ((state.brush_flow * 100.0).round() as u32).into()
+                }),
+        ))
+        .with_child(Slider::new().lens(AppState::brush_flow))
+        .with_child(Label::new(
+            LocalizedString::new("brush-hardness")
+                .with_arg("percent", |state: &AppState, _env| {
+                    ((state.brush_hardness * 100.0).round() as u32).into()
+                }),
+        ))
+        .with_child(Slider::new().lens(AppState::brush_hardness))
+        .with_child(Label::new(
+            LocalizedString::new("brush-step")
+                .with_arg("step", |state: &AppState, _env| ((state.brush_step * 10.0).round() / 10.0).into()),
+        ))
+        .with_child(Slider::new().with_range(0.0, 4.0).lens(AppState::brush_step))
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("brush-preview-title")).with_text_size(16.0))
+        .with_child(brush_preview)
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("brush-color-title")).with_text_size(16.0))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("color-r-placeholder"))
+                        .lens(AppState::color_r_input)
+                        .controller(TextBoxController::new(InputField::ColorR))
+                        .fix_width(60.0)
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("color-g-placeholder"))
+                        .lens(AppState::color_g_input)
+                        .controller(TextBoxController::new(InputField::ColorG))
+                        .fix_width(60.0)
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("color-b-placeholder"))
+                        .lens(AppState::color_b_input)
+                        .controller(TextBoxController::new(InputField::ColorB))
+                        .fix_width(60.0)
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("color-a-placeholder"))
+                        .lens(AppState::color_a_input)
+                        .controller(TextBoxController::new(InputField::ColorA))
+                        .fix_width(60.0)
+                )
+        )
+        .with_child(
+            TextBox::new()
+                .with_placeholder(LocalizedString::new("color-hex-placeholder"))
+                .lens(AppState::color_hex_input)
+                .controller(TextBoxController::new(InputField::ColorHex))
+                .fix_width(180.0)
+        )
+        .with_child(
+            Button::new(LocalizedString::new("brush-color-set-default"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    let mut settings = Settings::load();
+                    settings.default_brush_color = state.brush_color;
+                    state.status_message = match settings.save() {
+                        Ok(()) => StatusMessage::new("status-default-color-saved"),
+                        Err(err) => StatusMessage::new("status-settings-save-failed").with_arg("error", err),
+                    };
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("brush-color-swap"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    let secondary = state.secondary_color;
+                    state.secondary_color = state.brush_color;
+                    set_brush_color(state, secondary);
+                })
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("palette-title")).with_text_size(16.0))
+        .with_child(palette_swatches)
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new(LocalizedString::new("palette-load"))
+                        .on_click(|ctx, _state: &mut AppState, _env| {
+                            let options = FileDialogOptions::new()
+                                .allowed_types(vec![FileSpec::new("GIMP Palette", &["gpl"])])
+                                .accept_command(LOAD_PALETTE);
+                            ctx.submit_command(druid::commands::SHOW_OPEN_PANEL.with(options));
+                        })
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("palette-save-recent"))
+                        .on_click(|ctx, state: &mut AppState, _env| {
+                            if state.recent_colors.is_empty() {
+                                state.status_message = StatusMessage::new("status-no-recent-colors");
+                                return;
+                            }
+                            let options = FileDialogOptions::new()
+                                .allowed_types(vec![FileSpec::new("GIMP Palette", &["gpl"])])
+                                .default_name("recent.gpl")
+                                .accept_command(SAVE_PALETTE);
+                            ctx.submit_command(druid::commands::SHOW_SAVE_PANEL.with(options));
+                        })
+                )
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("recent-colors-title")).with_text_size(16.0))
+        .with_spacer(5.0)
+        .with_child(recent_swatches)
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("custom-palette-title")).with_text_size(16.0))
+        .with_child(custom_swatches)
+        .with_child(
+            Button::new(LocalizedString::new("custom-palette-add"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    if state.custom_palette.len() >= MAX_CUSTOM_SWATCHES {
+                        state.status_message = StatusMessage::new("status-custom-palette-full");
+                        return;
+                    }
+                    let color = state.brush_color;
+                    Arc::make_mut(&mut state.custom_palette).push(color);
+                    save_custom_palette(state);
+                })
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("remap-title")).with_text_size(16.0))
+        .with_child(
+            TextBox::new()
+                .with_placeholder(LocalizedString::new("remap-placeholder"))
+                .lens(AppState::remap_input)
+                .fix_width(180.0)
+        )
+        .with_child(
+            Button::new(LocalizedString::new("remap-apply"))
+                .on_click(|ctx, state: &mut AppState, _env| {
+                    let Some(mapping) = parse_color_mapping(&state.remap_input) else {
+                        state.status_message = StatusMessage::new("status-remap-invalid");
+                        return;
+                    };
+                    mark_image_changed(state);
+                    let mut image = state.image.write().unwrap();
+                    push_undo(&mut state.history, &state.image, &image);
+                    remap_colors(&mut image, &mapping);
+                    drop(image);
+                    state.status_message = StatusMessage::new("status-remapped").with_arg("count", mapping.len());
+                    ctx.request_paint();
+                })
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("pack-title")).with_text_size(16.0))
+        .with_child(Label::new(LocalizedString::new("pack-red")))
+        .with_child(channel_source_picker().lens(AppState::pack_red))
+        .with_child(Label::new(LocalizedString::new("pack-green")))
+        .with_child(channel_source_picker().lens(AppState::pack_green))
+        .with_child(Label::new(LocalizedString::new("pack-blue")))
+        .with_child(channel_source_picker().lens(AppState::pack_blue))
+        .with_child(Label::new(LocalizedString::new("pack-alpha")))
+        .with_child(channel_source_picker().lens(AppState::pack_alpha))
+        .with_child(
+            Button::new(LocalizedString::new("pack-apply"))
+                .on_click(|ctx, state: &mut AppState, _env| {
+                    let sources = [state.pack_red, state.pack_green, state.pack_blue, state.pack_alpha];
+                    mark_image_changed(state);
+                    let mut image = state.image.write().unwrap();
+                    push_undo(&mut state.history, &state.image, &image);
+                    *image = pack_channels(&image, sources);
+                    ctx.request_paint();
+                })
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("filters-title")).with_text_size(16.0))
+        .with_child(Label::new(
+            LocalizedString::new("filter-blur-radius")
+                .with_arg("radius", |state: &AppState, _env| (state.blur_radius.round() as u32).into()),
+        ))
+        .with_child(Slider::new().with_range(1.0, 20.0).lens(AppState::blur_radius))
+        .with_child(Label::new(
+            LocalizedString::new("filter-brightness")
+                .with_arg("value", |state: &AppState, _env| (state.brightness.round() as i32).into()),
+        ))
+        .with_child(Slider::new().with_range(-255.0, 255.0).lens(AppState::brightness))
+        .with_child(Label::new(
+            LocalizedString::new("filter-contrast")
+                .with_arg("value", |state: &AppState, _env| ((state.contrast * 100.0).round() / 100.0).into()),
+        ))
+        .with_child(Slider::new().with_range(0.0, 3.0).lens(AppState::contrast))
+        .with_child(Label::new(
+            LocalizedString::new("filter-hue")
+                .with_arg("value", |state: &AppState, _env| (state.hue_shift.round() as i32).into()),
+        ))
+        .with_child(Slider::new().with_range(-180.0, 180.0).lens(AppState::hue_shift))
+        .with_child(Label::new(
+            LocalizedString::new("filter-saturation")
+                .with_arg("value", |state: &AppState, _env| (state.saturation_shift.round() as i32).into()),
+        ))
+        .with_child(Slider::new().with_range(-100.0, 100.0).lens(AppState::saturation_shift))
+        .with_child(Label::new(
+            LocalizedString::new("filter-lightness")
+                .with_arg("value", |state: &AppState, _env| (state.lightness_shift.round() as i32).into()),
+        ))
+        .with_child(Slider::new().with_range(-100.0, 100.0).lens(AppState::lightness_shift))
+        .with_child(Label::new(
+            LocalizedString::new("filter-threshold-level")
+                .with_arg("level", |state: &AppState, _env| (state.threshold.round() as u32).into()),
+        ))
+        .with_child(Slider::new().with_range(0.0, 255.0).lens(AppState::threshold))
+        .with_child(Label::new(
+            LocalizedString::new("filter-posterize-levels")
+                .with_arg("levels", |state: &AppState, _env| (state.posterize_levels.round() as u32).into()),
+        ))
+        .with_child(Slider::new().with_range(2.0, 8.0).lens(AppState::posterize_levels))
+        .with_child(filter_buttons)
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("selection-title")).with_text_size(16.0))
+        .with_child(
+            Button::new(LocalizedString::new("selection-from-alpha"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    let image = state.image.read().unwrap();
+                    let mask = image.pixels().map(|pixel| pixel[3] as f32 / 255.0).collect();
+                    let selection = Selection::from_mask(mask, image.width());
+                    drop(image);
+                    state.selection = Some(Arc::new(selection));
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("selection-to-alpha"))
+                .on_click(|ctx, state: &mut AppState, _env| {
+                    let Some(selection) = state.selection.clone() else {
+                        state.status_message = StatusMessage::new("status-nothing-selected");
+                        return;
+                    };
+                    mark_image_changed(state);
+                    let mut image = state.image.write().unwrap();
+                    push_undo(&mut state.history, &state.image, &image);
+                    for (pixel, strength) in image.pixels_mut().zip(&selection.mask) {
+                        pixel[3] = (strength * 255.0).round() as u8;
+                    }
+                    ctx.request_paint();
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("selection-clear"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    state.selection = None;
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("selection-export"))
+                .on_click(|ctx, state: &mut AppState, _env| {
+                    if state.selection.as_ref().and_then(|selection| selection.bounds).is_none() {
+                        state.status_message = StatusMessage::new("status-nothing-selected");
+                        return;
+                    }
+                    let options = FileDialogOptions::new()
+                        .allowed_types(vec![FileSpec::PNG])
+                        .default_type(FileSpec::PNG)
+                        .default_name("selection.png")
+                        .accept_command(EXPORT_SELECTION);
+                    ctx.submit_command(druid::commands::SHOW_SAVE_PANEL.with(options));
+                })
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("layers-title")).with_text_size(16.0))
+        .with_child(Label::new(
+            LocalizedString::new("layers-active")
+                .with_arg("name", |state: &AppState, _env| {
+                    state.layers[state.active_layer].name.clone().into()
+                })
+                .with_arg("index", |state: &AppState, _env| (state.active_layer + 1).into())
+                .with_arg("count", |state: &AppState, _env| state.layers.len().into()),
+        ))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new(LocalizedString::new("layers-previous"))
+                        .on_click(|_ctx, state: &mut AppState, _env| {
+                            set_active_layer(state, state.active_layer.saturating_sub(1));
+                        })
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("layers-next"))
+                        .on_click(|_ctx, state: &mut AppState, _env| {
+                            set_active_layer(state, state.active_layer + 1);
+                        })
+                )
+        )
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new(LocalizedString::new("layers-add"))
+                        .on_click(|_ctx, state: &mut AppState, _env| {
+                            add_layer(state);
+                        })
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("layers-delete"))
+                        .on_click(|_ctx, state: &mut AppState, _env| {
+                            delete_layer(state);
+                        })
+                )
+        )
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new(LocalizedString::new("layers-move-up"))
+                        .on_click(|_ctx, state: &mut AppState, _env| {
+                            move_layer(state, true);
+                        })
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("layers-move-down"))
+                        .on_click(|_ctx, state: &mut AppState, _env| {
+                            move_layer(state, false);
+                        })
+                )
+        )
+        .with_child(Checkbox::new(LocalizedString::new("layers-visible")).lens(lens::Map::new(
+            |state: &AppState| state.layers[state.active_layer].visible,
+            |state: &mut AppState, visible| Arc::make_mut(&mut state.layers)[state.active_layer].visible = visible,
+        )))
+        .with_child(Label::new(
+            LocalizedString::new("layers-opacity")
+                .with_arg("percent", |state: &AppState, _env| {
+                    ((state.layers[state.active_layer].opacity * 100.0).round() as u32).into()
+                }),
+        ))
+        .with_child(Slider::new().lens(lens::Map::new(
+            |state: &AppState| state.layers[state.active_layer].opacity,
+            |state: &mut AppState, opacity| Arc::make_mut(&mut state.layers)[state.active_layer].opacity = opacity,
+        )))
+        .with_child(
+            RadioGroup::column(vec![
+                (LocalizedString::new("blend-normal"), BlendMode::Normal),
+                (LocalizedString::new("blend-multiply"), BlendMode::Multiply),
+                (LocalizedString::new("blend-screen"), BlendMode::Screen),
+                (LocalizedString::new("blend-overlay"), BlendMode::Overlay),
+                (LocalizedString::new("blend-add"), BlendMode::Add),
+            ])
+            .lens(lens::Map::new(
+                |state: &AppState| state.layers[state.active_layer].blend_mode,
+                |state: &mut AppState, mode| Arc::make_mut(&mut state.layers)[state.active_layer].blend_mode = mode,
+            ))
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("background-title")).with_text_size(16.0))
+        .with_child(
+            Button::new(LocalizedString::new("background-white"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    set_background_color(state, Color::WHITE);
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("background-gray"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    set_background_color(state, Color::rgb8(128, 128, 128));
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("background-transparent"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    set_background_color(state, Color::rgba8(0, 0, 0, 0));
+                })
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("new-document-title")).with_text_size(16.0))
+        .with_child(
+            RadioGroup::column(vec![
+                (LocalizedString::new("new-background-white"), NewBackground::White),
+                (LocalizedString::new("new-background-transparent"), NewBackground::Transparent),
+                (LocalizedString::new("new-background-custom"), NewBackground::Custom),
+            ])
+            .lens(AppState::new_background)
+        )
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("new-width-placeholder"))
+                        .lens(AppState::new_width_input)
+                        .fix_width(60.0)
+                )
+                .with_spacer(4.0)
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("new-height-placeholder"))
+                        .lens(AppState::new_height_input)
+                        .fix_width(60.0)
+                )
+        )
+        .with_child(
+            Button::new(LocalizedString::new("new-document"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    new_document(state);
+                })
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(LocalizedString::new("resize-title")).with_text_size(16.0))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("new-width-placeholder"))
+                        .lens(AppState::resize_width_input)
+                        .fix_width(60.0)
+                )
+                .with_spacer(4.0)
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("new-height-placeholder"))
+                        .lens(AppState::resize_height_input)
+                        .fix_width(60.0)
+                )
+        )
+        .with_child(Checkbox::new(LocalizedString::new("resize-lock-aspect")).lens(AppState::resize_lock_aspect))
+        .with_child(
+            RadioGroup::row(vec![
+                (LocalizedString::new("resize-nearest"), ResizeFilter::Nearest),
+                (LocalizedString::new("resize-triangle"), ResizeFilter::Triangle),
+                (LocalizedString::new("resize-lanczos"), ResizeFilter::Lanczos3),
+            ])
+            .lens(AppState::resize_filter)
+        )
+        .with_child(
+            Button::new(LocalizedString::new("resize-apply"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    resize_document(state);
+                })
+        )
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new(LocalizedString::new("transform-flip-horizontal"))
+                        .on_click(|_ctx, state: &mut AppState, _env| {
+                            map_layers(state, |_, image| flip_horizontal(image));
+                        })
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("transform-flip-vertical"))
+                        .on_click(|_ctx, state: &mut AppState, _env| {
+                            map_layers(state, |_, image| flip_vertical(image));
+                        })
+                )
+        )
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new(LocalizedString::new("transform-rotate-90"))
+                        .on_click(|_ctx, state: &mut AppState, _env| {
+                            map_layers(state, |_, image| rotate90(image));
+                        })
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("transform-rotate-180"))
+                        .on_click(|_ctx, state: &mut AppState, _env| {
+                            map_layers(state, |_, image| rotate180(image));
+                        })
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("transform-rotate-270"))
+                        .on_click(|_ctx, state: &mut AppState, _env| {
+                            map_layers(state, |_, image| rotate270(image));
+                        })
+                )
+        )
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("transform-rotate-placeholder"))
+                        .lens(AppState::rotate_degrees_input)
+                        .fix_width(60.0)
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("transform-rotate"))
+                        .on_click(|_ctx, state: &mut AppState, _env| {
+                            rotate_document(state);
+                        })
+                )
+        )
+        .with_spacer(10.0)
+        .with_child(
+            Button::new(LocalizedString::new("file-open-image"))
+                .on_click(|ctx, _state: &mut AppState, _env| {
+                    let options = FileDialogOptions::new()
+                        .allowed_types(vec![
+                            FileSpec::PNG,
+                            FileSpec::JPG,
+                            FileSpec::GIF,
+                            FileSpec::new("All files", &["*"]),
+                        ])
+                        .accept_command(OPEN_IMAGE);
+                    ctx.submit_command(druid::commands::SHOW_OPEN_PANEL.with(options));
+                })
+        )
+        .with_child(
+            TextBox::new()
+                .with_placeholder(LocalizedString::new("file-url-placeholder"))
+                .lens(AppState::url_input)
+                .fix_width(180.0)
+        )
+        .with_child(
+            Button::new(LocalizedString::new("file-open-url"))
+                .on_click(|ctx, state: &mut AppState, _env| {
+                    let url = state.url_input.trim().to_string();
+                    if url.is_empty() {
+                        return;
+                    }
+                    state.status_message = StatusMessage::new("status-url-downloading").with_arg("url", &url);
+                    let sink = ctx.get_external_handle();
+                    thread::spawn(move || {
+                        let result = fetch_image(&url);
+                        let _ = sink.submit_command(URL_IMAGE_LOADED, result, Target::Auto);
+                    });
+                })
+        )
+        .with_child(
+            Button::new(LocalizedString::new("file-save-image"))
+                .on_click(|ctx, _state: &mut AppState, _env| {
+                    ctx.submit_command(druid::commands::SHOW_SAVE_PANEL.with(save_image_options()));
+                })
+        )
+        .with_child(Label::new(
+            LocalizedString::new("file-jpeg-quality")
+                .with_arg("quality", |state: &AppState, _env| (state.jpeg_quality.round() as u32).into()),
+        ))
+        .with_child(Slider::new().with_range(1.0, 100.0).lens(AppState::jpeg_quality))
+        .with_child(
+            Checkbox::new(LocalizedString::new("file-export-premultiplied"))
+                .lens(AppState::export_premultiplied)
+        )
+        .with_child(
+            Button::new(LocalizedString::new("file-export-view"))
+                .on_click(|ctx, _state: &mut AppState, _env| {
+                    let options = FileDialogOptions::new()
+                        .allowed_types(vec![FileSpec::PNG])
+                        .default_name("view.png")
+                        .accept_command(EXPORT_VIEW);
+                    ctx.submit_command(druid::commands::SHOW_SAVE_PANEL.with(options));
+                })
+        )
+        .with_child(
+            RadioGroup::row(vec![
+                (LocalizedString::new("channel-red"), Channel::Red),
+                (LocalizedString::new("channel-green"), Channel::Green),
+                (LocalizedString::new("channel-blue"), Channel::Blue),
+                (LocalizedString::new("channel-alpha"), Channel::Alpha),
+            ])
+            .lens(AppState::export_channel)
+        )
+        .with_child(
+            Button::new(LocalizedString::new("file-export-channel"))
+                .on_click(|ctx, _state: &mut AppState, _env| {
+                    let options = FileDialogOptions::new()
+                        .allowed_types(vec![FileSpec::PNG])
+                        .default_name("channel.png")
+                        .accept_command(EXPORT_CHANNEL);
+                    ctx.submit_command(druid::commands::SHOW_SAVE_PANEL.with(options));
+                })
+        )
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("tile-width-placeholder"))
+                        .lens(AppState::tile_width_input)
+                        .fix_width(60.0)
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("tile-height-placeholder"))
+                        .lens(AppState::tile_height_input)
+                        .fix_width(60.0)
+                )
+        )
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("tile-margin-placeholder"))
+                        .lens(AppState::tile_margin_input)
+                        .fix_width(60.0)
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder(LocalizedString::new("tile-spacing-placeholder"))
+                        .lens(AppState::tile_spacing_input)
+                        .fix_width(60.0)
+                )
+        )
+        .with_child(
+            Button::new(LocalizedString::new("file-export-tiles"))
+                .on_click(|ctx, _state: &mut AppState, _env| {
+                    let options = FileDialogOptions::new()
+                        .allowed_types(vec![FileSpec::PNG])
+                        .default_name("tile.png")
+                        .accept_command(EXPORT_TILES);
+                    ctx.submit_command(druid::commands::SHOW_SAVE_PANEL.with(options));
+                })
+        )
+        .with_child(Either::new(
+            |state: &AppState, _env| state.clear_pending,
+            Flex::column()
+                .with_child(Label::new(LocalizedString::new("clear-confirm-question")))
+                .with_child(
+                    Flex::row()
+                        .with_child(
+                            Button::new(LocalizedString::new("clear-confirm-yes"))
+                                .on_click(|_ctx, state: &mut AppState, _env| {
+                                    clear_canvas(state);
+                                    state.clear_pending = false;
+                                })
+                        )
+                        .with_child(
+                            Button::new(LocalizedString::new("clear-confirm-cancel"))
+                                .on_click(|_ctx, state: &mut AppState, _env| {
+                                    state.clear_pending = false;
+                                })
+                        )
+                ),
+            Button::new(LocalizedString::new("clear-canvas"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    request_clear_canvas(state);
+                }),
+        ))
+        .with_child(
+            Button::new(LocalizedString::new("settings-reset"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    reset_settings(state);
+                })
+        )
+        .with_child(Either::new(
+            |state: &AppState, _env| state.exit_pending,
+            Flex::column()
+                .with_child(Label::new(LocalizedString::new("exit-confirm-question")))
+                .with_child(
+                    Flex::row()
+                        .with_child(
+                            Button::new(LocalizedString::new("exit-confirm-save"))
+                                .on_click(|ctx, state: &mut AppState, _env| {
+                                    state.exit_pending = false;
+                                    state.exit_after_save = true;
+                                    ctx.submit_command(druid::commands::SHOW_SAVE_PANEL.with(save_image_options()));
+                                })
+                        )
+                        .with_child(
+                            Button::new(LocalizedString::new("exit-confirm-discard"))
+                                .on_click(|_ctx, _state: &mut AppState, _env| {
+                                    std::process::exit(0);
+                                })
+                        )
+                        .with_child(
+                            Button::new(LocalizedString::new("exit-confirm-cancel"))
+                                .on_click(|_ctx, state: &mut AppState, _env| {
+                                    state.exit_pending = false;
+                                })
+                        )
+                ),
+            Button::new(LocalizedString::new("exit"))
+                .on_click(|_ctx, state: &mut AppState, _env| {
+                    if state.dirty {
+                        state.exit_pending = true;
+                    } else {
+                        std::process::exit(0);
+                    }
+                }),
+        ))
+        .padding(10.0)
+        .fix_width(200.0);
+
+    let workspace = Flex::row()
+        .with_child(toolbar)
+        .with_flex_spacer(1.0)
+        .with_child(Align::centered(canvas))
+        .with_flex_spacer(1.0)
+        .with_child(navigator);
+
+    let cursor_status = Either::new(
+        |state: &AppState, _env| pixel_under_cursor(state).is_some(),
+        Label::new(
+            LocalizedString::new("status-cursor")
+                .with_arg("x", |state: &AppState, _env| pixel_under_cursor(state).map_or(0, |(x, _, _)| x).into())
+                .with_arg("y", |state: &AppState, _env| pixel_under_cursor(state).map_or(0, |(_, y, _)| y).into())
+                .with_arg("rgba", |state: &AppState, _env| {
+                    let Some((_, _, pixel)) = pixel_under_cursor(state) else {
+                        return String::new().into();
+                    };
+                    format!("{}, {}, {}, {}", pixel[0], pixel[1], pixel[2], pixel[3]).into()
+                }),
+        ),
+        Label::new(LocalizedString::new("status-cursor-none")),
+    );
+
+    Flex::column()
+        .with_flex_child(workspace, 1.0)
+        .with_child(
+            Flex::row()
+                .with_child(Label::dynamic(|state: &AppState, env| state.status_message.localize(env)))
+                .with_flex_spacer(1.0)
+                .with_child(cursor_status)
+        )
+        .padding(10.0)
+}
+
+fn save_image_options() -> FileDialogOptions {
+    let jpeg = FileSpec::new("JPEG", &["jpg", "jpeg"]);
+    let bmp = FileSpec::new("BMP", &["bmp"]);
+    FileDialogOptions::new()
+        .allowed_types(vec![FileSpec::PNG, jpeg, bmp])
+        .default_type(FileSpec::PNG)
+        .default_name("output.png")
+        .accept_command(SAVE_IMAGE)
+}
+
+fn channel_source_picker() -> impl Widget<ChannelSource> {
+    RadioGroup::row(vec![
+        (LocalizedString::new("channel-red"), ChannelSource::Red),
+        (LocalizedString::new("channel-green"), ChannelSource::Green),
+        (LocalizedString::new("channel-blue"), ChannelSource::Blue),
+        (LocalizedString::new("channel-alpha"), ChannelSource::Alpha),
+        (LocalizedString::new("channel-zero"), ChannelSource::Zero),
+        (LocalizedString::new("channel-full"), ChannelSource::Full),
+    ])
+}
+
+fn draw_dashed_rect(ctx: &mut PaintCtx, state: &AppState, rect: Rect) {
+    let outline = Rect::from_points(
+        image_to_screen(state, rect.origin()),
+        image_to_screen(state, Point::new(rect.x1, rect.y1)),
+    );
+    ctx.stroke(outline, &Color::WHITE, 1.0);
+    ctx.stroke_styled(outline, &Color::BLACK, 1.0, &StrokeStyle::new().dash_pattern(&[4.0, 4.0]));
+}
+
+fn draw_loupe(ctx: &mut PaintCtx, state: &AppState, image: &RgbaImage, cursor: Point) {
+    let side = LOUPE_RADIUS * 2 + 1;
+    let (center_x, center_y) = (cursor.x.floor() as i64, cursor.y.floor() as i64);
+    let crop = RgbaImage::from_fn(side, side, |x, y| {
+        let source_x = center_x + x as i64 - LOUPE_RADIUS as i64;
+        let source_y = center_y + y as i64 - LOUPE_RADIUS as i64;
+        if source_x < 0 || source_y < 0 || source_x >= image.width() as i64 || source_y >= image.height() as i64 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        *image.get_pixel(source_x as u32, source_y as u32)
+    });
+
+    let bounds = ctx.size().to_rect();
+    let size = side as f64 * LOUPE_SCALE;
+    let screen_cursor = image_to_screen(state, cursor);
+    let origin = Point::new(
+        (screen_cursor.x + 20.0).min(bounds.width() - size - 4.0),
+        (screen_cursor.y + 20.0).min(bounds.height() - size - 24.0),
+    );
+    let loupe_rect = Rect::from_origin_size(origin, (size, size));
+    ctx.fill(loupe_rect, &APP_BACKDROP);
+    draw_checkerboard(ctx, loupe_rect);
+    let piet_image = ctx
+        .make_image(side as usize, side as usize, crop.as_raw(), ImageFormat::RgbaSeparate)
+        .unwrap();
+    ctx.draw_image(&piet_image, loupe_rect, druid::piet::InterpolationMode::NearestNeighbor);
+    let center_rect = Rect::from_origin_size(
+        origin + (LOUPE_RADIUS as f64 * LOUPE_SCALE, LOUPE_RADIUS as f64 * LOUPE_SCALE),
+        (LOUPE_SCALE, LOUPE_SCALE),
+    );
+    ctx.stroke(center_rect, &Color::rgb8(255, 0, 0), 1.0);
+    ctx.stroke(loupe_rect, &Color::WHITE, 1.0);
+
+    let [r, g, b, a] = crop.get_pixel(LOUPE_RADIUS, LOUPE_RADIUS).0;
+    let layout = ctx
+        .text()
+        .new_text_layout(format!("{}, {}, {}, {}", r, g, b, a))
+        .text_color(Color::WHITE)
+        .build()
+        .unwrap();
+    let label_rect = Rect::from_origin_size(origin + (0.0, size), (size, layout.size().height + 4.0));
+    ctx.fill(label_rect, &Color::rgba8(0, 0, 0, 160));
+    ctx.draw_text(&layout, origin + (4.0, size + 2.0));
+}
+
+fn draw_checkerboard(ctx: &mut PaintCtx, bounds: Rect) {
+    let cell = 8.0;
+    ctx.fill(bounds, &Color::rgb8(255, 255, 255));
+    let columns = (bounds.width() / cell).ceil() as usize;
+    let rows = (bounds.height() / cell).ceil() as usize;
+    for row in 0..rows {
+        for column in (row % 2..columns).step_by(2) {
+            let square = Rect::from_origin_size(
+                (bounds.x0 + column as f64 * cell, bounds.y0 + row as f64 * cell),
+                (cell, cell),
+            )
+            .intersect(bounds);
+            ctx.fill(square, &Color::rgb8(204, 204, 204));
+        }
+    }
+}
+
+fn premultiply_alpha(image: &RgbaImage) -> RgbaImage {
+    let mut premultiplied = image.clone();
+    for pixel in premultiplied.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for channel in 0..3 {
+            pixel[channel] = ((pixel[channel] as u32 * alpha + 127) / 255) as u8;
+        }
+    }
+    premultiplied
+}
+
+fn checker_color(x: u32, y: u32) -> Rgba<u8> {
+    if (x / 8 + y / 8).is_multiple_of(2) {
+        Rgba([204, 204, 204, 255])
+    } else {
+        Rgba([255, 255, 255, 255])
+    }
+}
+
+fn extract_channel(image: &RgbaImage, channel: Channel) -> RgbaImage {
+    let index = match channel {
+        Channel::Red => 0,
+        Channel::Green => 1,
+        Channel::Blue => 2,
+        Channel::Alpha => 3,
+    };
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let value = image.get_pixel(x, y)[index];
+        Rgba([value, value, value, 255])
+    })
+}
+
+fn pack_channels(image: &RgbaImage, sources: [ChannelSource; 4]) -> RgbaImage {
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let pixel = image.get_pixel(x, y);
+        Rgba(sources.map(|source| match source {
+            ChannelSource::Red => pixel[0],
+            ChannelSource::Green => pixel[1],
+            ChannelSource::Blue => pixel[2],
+            ChannelSource::Alpha => pixel[3],
+            ChannelSource::Zero => 0,
+            ChannelSource::Full => 255,
+        }))
+    })
+}
+
+fn save_document(state: &mut AppState, path: &Path) -> Result<(), ImageError> {
+    let image = composite_layers(&state.layers);
+    let quality = state.jpeg_quality.round() as u8;
+    if state.export_premultiplied {
+        save_image(&premultiply_alpha(&image), path, quality)?;
+    } else {
+        save_image(&image, path, quality)?;
+    }
+    state.dirty = false;
+    Ok(())
+}
+
+fn save_image(image: &RgbaImage, path: &Path, jpeg_quality: u8) -> Result<(), ImageError> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("jpg" | "jpeg") => {
+            let rgb = DynamicImage::ImageRgba8(image.clone()).to_rgb8();
+            let file = File::create(path).map_err(ImageError::IoError)?;
+            JpegEncoder::new_with_quality(file, jpeg_quality).encode_image(&rgb)
+        }
+        _ => image.save(path),
+    }
+}
+
+struct TileGrid {
+    tile_width: u32,
+    tile_height: u32,
+    margin: u32,
+    spacing: u32,
+}
+
+fn parse_tile_grid(state: &AppState) -> Result<TileGrid, StatusMessage> {
+    let parse = |input: &str, key: &'static str| {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(0);
+        }
+        input
+            .parse::<u32>()
+            .map_err(|_| StatusMessage::new(key))
+    };
+    let grid = TileGrid {
+        tile_width: parse(&state.tile_width_input, "status-tile-width-invalid")?,
+        tile_height: parse(&state.tile_height_input, "status-tile-height-invalid")?,
+        margin: parse(&state.tile_margin_input, "status-tile-margin-invalid")?,
+        spacing: parse(&state.tile_spacing_input, "status-tile-spacing-invalid")?,
+    };
+    if grid.tile_width == 0 || grid.tile_height == 0 {
+        return Err(StatusMessage::new("status-tile-size-zero"));
+    }
+    Ok(grid)
+}
+
+fn export_tiles(image: &RgbaImage, path: &Path, grid: TileGrid) -> Result<usize, ImageError> {
+    let (width, height) = image.dimensions();
+    let cells = |length: u32, tile: u32| {
+        let usable = length.saturating_sub(grid.margin * 2);
+        if usable < tile { 0 } else { (usable - tile) / (tile + grid.spacing) + 1 }
+    };
+    let columns = cells(width, grid.tile_width);
+    let rows = cells(height, grid.tile_height);
+    let count = (columns * rows) as usize;
+    let digits = count.to_string().len();
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("tile");
+    let directory = path.parent().unwrap_or(Path::new("."));
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let x = grid.margin + column * (grid.tile_width + grid.spacing);
+            let y = grid.margin + row * (grid.tile_height + grid.spacing);
+            let index = (row * columns + column) as usize;
+            let tile = crop_imm(image, x, y, grid.tile_width, grid.tile_height).to_image();
+            tile.save(directory.join(format!("{}_{:0digits$}.png", stem, index, digits = digits)))?;
+        }
+    }
+    Ok(count)
+}
+
+fn render_view(state: &AppState) -> RgbaImage {
+    let image = composite_layers(&state.layers);
+    let (width, height) = image.dimensions();
+    let (r, g, b, a) = APP_BACKDROP.as_rgba8();
+    let view_rect =
+        image_rect(state, &image).intersect(Rect::new(0.0, 0.0, state.canvas_width, state.canvas_height));
+
+    RgbaImage::from_fn(state.canvas_width as u32, state.canvas_height as u32, |x, y| {
+        let screen_point = Point::new(x as f64 + 0.5, y as f64 + 0.5);
+        if !view_rect.contains(screen_point) {
+            return Rgba([r, g, b, a]);
+        }
+        let offset = screen_point - view_rect.origin();
+        let mut pixel = checker_color(offset.x as u32, offset.y as u32);
+        let image_point = screen_to_image(state, screen_point);
+        let source = *image.get_pixel(
+            (image_point.x as u32).min(width - 1),
+            (image_point.y as u32).min(height - 1),
+        );
+        blend_pixel(&mut pixel, source);
+        pixel
+    })
+}
+
+fn swatch_rect(index: usize) -> Rect {
+    let origin = Point::new(
+        (index % SWATCHES_PER_ROW) as f64 * SWATCH_SIZE,
+        (index / SWATCHES_PER_ROW) as f64 * SWATCH_SIZE,
+    );
+    Rect::from_origin_size(origin, (SWATCH_SIZE, SWATCH_SIZE))
+}
+
+fn swatch_at<T>(palette: &[T], pos: Point) -> Option<usize> {
+    (0..palette.len()).find(|index| swatch_rect(*index).contains(pos))
+}
+
+// Resets tool, brush, color and filter settings one field at a time; the document,
+// selection, clipboard, history and recent colors are left alone.
+fn reset_settings(state: &mut AppState) {
+    let settings = Settings {
+        custom_palette: state.custom_palette.to_vec(),
+        ..Settings::default()
+    };
+    let saved = settings.save();
+
+    let color = settings.default_brush_color;
+    let (r, g, b, a) = color.as_rgba8();
+    state.brush_color = color;
+    state.color_r_input = r.to_string();
+    state.color_g_input = g.to_string();
+    state.color_b_input = b.to_string();
+    state.color_a_input = a.to_string();
+    state.color_hex_input = format_hex_color(color);
+    state.secondary_color = Color::WHITE;
+    state.gradient_end_color = Color::WHITE;
+    state.gradient_end_input = format_hex_color(Color::WHITE);
+    state.gradient_mode = GradientMode::Linear;
+
+    state.brush_size = 5;
+    state.brush_size_input = "5".to_string();
+    state.brush_antialiasing = false;
+    state.brush_opacity = 1.0;
+    state.brush_flow = 1.0;
+    state.brush_hardness = 1.0;
+    state.brush_step = 0.5;
+    state.spray_density = 20.0;
+    state.tool_settings = Arc::new(HashMap::new());
+    state.erase_mode = EraseMode::Transparent;
+    state.brush_shape = BrushShape::Square;
+    state.brush_stamp = None;
+    state.brush_angle = 0.0;
+    state.rotate_to_direction = false;
+    state.dynamic_size = false;
+    state.falloff_curve = FalloffCurve::Linear;
+    state.symmetry = Symmetry::None;
+    state.split_long_strokes = false;
+    state.stamp_interp = StampInterp::Nearest;
+
+    state.fill_tolerance = 0;
+    state.fill_tolerance_input = "0".to_string();
+    state.fill_feather = 0;
+    state.fill_feather_input = "0".to_string();
+    state.shape_fill = false;
+    state.picker_sample_size = 1;
+    state.picker_switch_back = false;
+    state.picker_merged = true;
+
+    state.blur_radius = 2.0;
+    state.brightness = 0.0;
+    state.contrast = 1.0;
+    state.threshold = 128.0;
+    state.posterize_levels = 4.0;
+    state.hue_shift = 0.0;
+    state.saturation_shift = 0.0;
+    state.lightness_shift = 0.0;
+
+    set_zoom(state, 1.0);
+    state.status_message = match saved {
+        Ok(()) => StatusMessage::new("status-settings-reset"),
+        Err(err) => StatusMessage::new("status-settings-reset-save-failed").with_arg("error", err),
+    };
+}
+
+fn save_custom_palette(state: &mut AppState) {
+    let mut settings = Settings::load();
+    settings.custom_palette = state.custom_palette.to_vec();
+    if let Err(err) = settings.save() {
+        state.status_message = StatusMessage::new("status-palette-save-failed").with_arg("error", err);
+    }
+}
+
+fn new_document(state: &mut AppState) {
+    let background = match state.new_background {
+        NewBackground::White => Color::WHITE,
+        NewBackground::Transparent => Color::rgba8(0, 0, 0, 0),
+        NewBackground::Custom => state.secondary_color,
+    };
+    let parse = |input: &str| {
+        input
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|size| (1..=MAX_CANVAS_DIMENSION).contains(size))
+    };
+    let (Some(width), Some(height)) = (parse(&state.new_width_input), parse(&state.new_height_input)) else {
+        state.status_message = StatusMessage::new("status-document-size-invalid").with_arg("max", MAX_CANVAS_DIMENSION);
+        return;
+    };
+    replace_document(state, create_blank_image(width, height, background));
+    state.background_color = background;
+    state.status_message = StatusMessage::new("status-document-new")
+        .with_arg("width", width)
+        .with_arg("height", height);
+}
+
+fn resize_document(state: &mut AppState) {
+    let (old_width, old_height) = state.image.read().unwrap().dimensions();
+    let parse = |input: &str| {
+        input
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|size| (1..=MAX_CANVAS_DIMENSION).contains(size))
+    };
+    let Some(width) = parse(&state.resize_width_input) else {
+        state.status_message = StatusMessage::new("status-resize-width-invalid").with_arg("max", MAX_CANVAS_DIMENSION);
+        return;
+    };
+    let height = if state.resize_lock_aspect {
+        ((width as f64 * old_height as f64 / old_width as f64).round() as u32).clamp(1, MAX_CANVAS_DIMENSION)
+    } else {
+        let Some(height) = parse(&state.resize_height_input) else {
+            state.status_message =
+                StatusMessage::new("status-resize-height-invalid").with_arg("max", MAX_CANVAS_DIMENSION);
+            return;
+        };
+        height
+    };
+    let filter = match state.resize_filter {
+        ResizeFilter::Nearest => FilterType::Nearest,
+        ResizeFilter::Triangle => FilterType::Triangle,
+        ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+    };
+
+    map_layers(state, |_, image| resize(image, width, height, filter));
+    state.resize_height_input = height.to_string();
+    state.status_message = StatusMessage::new("status-resized").with_arg("width", width).with_arg("height", height);
+}
+
+fn rotate_document(state: &mut AppState) {
+    let Ok(degrees) = state.rotate_degrees_input.trim().parse::<f64>() else {
+        state.status_message = StatusMessage::new("status-rotate-invalid");
+        return;
+    };
+    let (r, g, b, a) = state.background_color.as_rgba8();
+    let background = Rgba([r, g, b, a]);
+    map_layers(state, |index, image| {
+        let fill = if index == 0 { background } else { Rgba([0, 0, 0, 0]) };
+        rotate_arbitrary(image, degrees, fill)
+    });
+    state.status_message = StatusMessage::new("status-rotated").with_arg("degrees", degrees);
+}
+
+fn rotate_arbitrary(image: &RgbaImage, degrees: f64, fill: Rgba<u8>) -> RgbaImage {
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let rotated_width = (width * cos.abs() + height * sin.abs()).round().max(1.0);
+    let rotated_height = (width * sin.abs() + height * cos.abs()).round().max(1.0);
+    let sample = |x: i64, y: i64| {
+        if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+            fill
+        } else {
+            *image.get_pixel(x as u32, y as u32)
+        }
+    };
+
+    RgbaImage::from_fn(rotated_width as u32, rotated_height as u32, |x, y| {
+        let dx = x as f64 + 0.5 - rotated_width / 2.0;
+        let dy = y as f64 + 0.5 - rotated_height / 2.0;
+        let source_x = dx * cos + dy * sin + width / 2.0 - 0.5;
+        let source_y = dy * cos - dx * sin + height / 2.0 - 0.5;
+        let (x0, y0) = (source_x.floor(), source_y.floor());
+        let (fx, fy) = ((source_x - x0) as f32, (source_y - y0) as f32);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let mut top = sample(x0, y0);
+        mix_pixel(&mut top, sample(x0 + 1, y0), fx);
+        let mut bottom = sample(x0, y0 + 1);
+        mix_pixel(&mut bottom, sample(x0 + 1, y0 + 1), fx);
+        mix_pixel(&mut top, bottom, fy);
+        top
+    })
+}
+
+fn map_layers(state: &mut AppState, transform: impl Fn(usize, &RgbaImage) -> RgbaImage) {
+    let old_dimensions = state.image.read().unwrap().dimensions();
+    push_layers_undo(state);
+    let layers = state
+        .layers
+        .iter()
+        .enumerate()
+        .map(|(index, layer)| Layer {
+            image: Arc::new(RwLock::new(transform(index, &layer.image.read().unwrap()))),
+            ..layer.clone()
+        })
+        .collect();
+    state.layers = Arc::new(layers);
+    set_active_layer(state, state.active_layer);
+    state.selection = None;
+    state.floating = None;
+    state.polygon_points = Arc::new(Vec::new());
+    if state.image.read().unwrap().dimensions() != old_dimensions {
+        fit_to_view(state);
+    }
+}
+
+fn crop_document(state: &mut AppState, region: Rect) {
+    let (width, height) = state.image.read().unwrap().dimensions();
+    let region = region.round().intersect(Rect::new(0.0, 0.0, width as f64, height as f64));
+    if region.width() < 1.0 || region.height() < 1.0 {
+        state.status_message = StatusMessage::new("status-crop-empty");
+        return;
+    }
+    let (x, y) = (region.x0 as u32, region.y0 as u32);
+    let (crop_width, crop_height) = (region.width() as u32, region.height() as u32);
+
+    map_layers(state, |_, image| crop_imm(image, x, y, crop_width, crop_height).to_image());
+    state.status_message = StatusMessage::new("status-cropped")
+        .with_arg("width", crop_width)
+        .with_arg("height", crop_height);
+}
+
+fn create_blank_image(width: u32, height: u32, color: Color) -> RgbaImage {
+    let (r, g, b, a) = color.as_rgba8();
+    RgbaImage::from_pixel(width, height, Rgba([r, g, b, a]))
+}
+
+fn set_background_color(state: &mut AppState, color: Color) {
+    state.background_color = color;
+    mark_image_changed(state);
+    let mut image = state.image.write().unwrap();
+    let (r, g, b, a) = color.as_rgba8();
+    for pixel in image.pixels_mut() {
+        *pixel = Rgba([r, g, b, a]);
+    }
+}
+
+fn open_image(path: &Path) -> OpenResult {
+    match ImageReader::open(path)?.with_guessed_format()?.decode() {
+        Ok(image) => Ok((image.to_rgba8(), None)),
+        Err(err) => match decode_partial_image(path) {
+            Some(image) => Ok((image, Some(err))),
+            None => Err(err),
+        },
+    }
+}
+
+fn fetch_image(url: &str) -> Result<RgbaImage, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(URL_TIMEOUT)
+        .build()
+        .map_err(|err| err.to_string())?;
+    let response = client
+        .get(url)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.to_string())?;
+    let bytes = response.bytes().map_err(|err| err.to_string())?;
+    image::load_from_memory(&bytes)
+        .map(|image| image.to_rgba8())
+        .map_err(|err| err.to_string())
+}
+
+fn decode_partial_image(path: &Path) -> Option<RgbaImage> {
+    let format = ImageReader::open(path).ok()?.with_guessed_format().ok()?.format()?;
+    let reader = BufReader::new(File::open(path).ok()?);
+    match format {
+        image::ImageFormat::Png => read_partial(PngDecoder::new(reader).ok()?),
+        image::ImageFormat::Bmp => read_partial(BmpDecoder::new(reader).ok()?),
+        _ => None,
+    }
+}
+
+fn read_partial<'a>(decoder: impl ImageDecoder<'a>) -> Option<RgbaImage> {
+    let (width, height) = decoder.dimensions();
+    let color_type = decoder.color_type();
+    let mut buffer = vec![0u8; usize::try_from(decoder.total_bytes()).ok()?];
+    // Rows decoded before the error are kept; the rest of the buffer stays zeroed.
+    let _ = decoder.read_image(&mut buffer);
+    let image = match color_type {
+        ColorType::Rgba8 => DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, buffer)?),
+        ColorType::Rgb8 => DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, buffer)?),
+        ColorType::La8 => DynamicImage::ImageLumaA8(GrayAlphaImage::from_raw(width, height, buffer)?),
+        ColorType::L8 => DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, buffer)?),
+        _ => return None,
+    };
+    Some(image.to_rgba8())
+}
+
+fn image_rect(state: &AppState, image: &RgbaImage) -> Rect {
+    Rect::from_origin_size(
+        state.pan_offset,
+        (image.width() as f64 * state.zoom, image.height() as f64 * state.zoom),
+    )
+}
+
+fn screen_to_image(state: &AppState, pos: Point) -> Point {
+    Point::new(
+        (pos.x - state.pan_offset.x) / state.zoom,
+        (pos.y - state.pan_offset.y) / state.zoom,
+    )
+}
+
+fn pixel_under_cursor(state: &AppState) -> Option<(u32, u32, Rgba<u8>)> {
+    let cursor = state.cursor_pos?;
+    let (width, height) = state.image.read().unwrap().dimensions();
+    if cursor.x < 0.0 || cursor.y < 0.0 || cursor.x >= width as f64 || cursor.y >= height as f64 {
+        return None;
+    }
+    let (x, y) = (cursor.x as u32, cursor.y as u32);
+    // The status bar reports what is on screen, so every visible layer is blended in.
+    let pixel = composite_region(&state.layers, (x, y, 1, 1), None);
+    Some((x, y, *pixel.get_pixel(0, 0)))
+}
+
+fn image_to_screen(state: &AppState, pos: Point) -> Point {
+    Point::new(
+        pos.x * state.zoom + state.pan_offset.x,
+        pos.y * state.zoom + state.pan_offset.y,
+    )
+}
+
+fn minimap_viewport(state: &AppState, image: &RgbaImage) -> Rect {
+    let image_rect = image_rect(state, image);
+    let visible = Rect::new(0.0, 0.0, state.canvas_width, state.canvas_height).intersect(image_rect);
+    Rect::new(
+        (visible.x0 - image_rect.x0) / image_rect.width() * MINIMAP_WIDTH,
+        (visible.y0 - image_rect.y0) / image_rect.height() * MINIMAP_HEIGHT,
+        (visible.x1 - image_rect.x0) / image_rect.width() * MINIMAP_WIDTH,
+        (visible.y1 - image_rect.y0) / image_rect.height() * MINIMAP_HEIGHT,
+    )
+}
+
+fn set_zoom(state: &mut AppState, zoom: f64) {
+    let center = Point::new(state.canvas_width / 2.0, state.canvas_height / 2.0);
+    zoom_around(state, zoom, center);
+}
+
+fn apply_zoom_input(state: &mut AppState) {
+    match state.zoom_input.trim().trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if percent.is_finite() => set_zoom(state, percent / 100.0),
+        _ => state.status_message = StatusMessage::new("status-zoom-invalid"),
+    }
+}
+
+fn zoom_around(state: &mut AppState, zoom: f64, anchor: Point) {
+    if !zoom.is_finite() {
+        return;
+    }
+    let zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+    let ratio = zoom / state.zoom;
+    state.pan_offset = anchor - (anchor - state.pan_offset) * ratio;
+    state.zoom = zoom;
+    state.zoom_input = format!("{}", (zoom * 100.0).round());
+}
+
+fn clamp_pan(state: &mut AppState) {
+    let (width, height) = state.image.read().unwrap().dimensions();
+    let (scaled_width, scaled_height) = (width as f64 * state.zoom, height as f64 * state.zoom);
+    let margin_x = PAN_MARGIN.min(scaled_width);
+    let margin_y = PAN_MARGIN.min(scaled_height);
+    let x = state.pan_offset.x.clamp(margin_x - scaled_width, state.canvas_width - margin_x);
+    let y = state.pan_offset.y.clamp(margin_y - scaled_height, state.canvas_height - margin_y);
+    state.pan_offset = Point::new(x, y);
+}
+
+fn pan_to_minimap_point(state: &mut AppState, pos: Point) {
+    let (width, height) = state.image.read().unwrap().dimensions();
+    let u = (pos.x / MINIMAP_WIDTH).clamp(0.0, 1.0);
+    let v = (pos.y / MINIMAP_HEIGHT).clamp(0.0, 1.0);
+    state.pan_offset = Point::new(
+        state.canvas_width / 2.0 - u * width as f64 * state.zoom,
+        state.canvas_height / 2.0 - v * height as f64 * state.zoom,
+    );
+}
+
+fn fit_to_view(state: &mut AppState) {
+    let (width, height) = state.image.read().unwrap().dimensions();
+    let zoom = (state.canvas_width / width as f64).min(state.canvas_height / height as f64);
+    state.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+    state.zoom_input = format!("{}", (state.zoom * 100.0).round());
+    state.pan_offset = Point::new(
+        (state.canvas_width - width as f64 * state.zoom) / 2.0,
+        (state.canvas_height - height as f64 * state.zoom) / 2.0,
+    );
+}
+
+fn apply_brush_size_input(state: &mut AppState) {
+    match state.brush_size_input.trim().parse::<u32>() {
+        Ok(size) if (1..=MAX_BRUSH_SIZE).contains(&size) => state.brush_size = size,
+        _ => state.status_message = StatusMessage::new("status-brush-size-invalid").with_arg("max", MAX_BRUSH_SIZE),
+    }
+}
+
+fn dynamic_brush_size(brush_size: u32, distance: f64, elapsed: Duration) -> u32 {
+    let millis = elapsed.as_secs_f64() * 1000.0;
+    if millis <= 0.0 {
+        return brush_size;
+    }
+    let speed = distance / millis;
+    let size = brush_size as f64 / (1.0 + speed / DYNAMIC_SIZE_SPEED);
+    (size.round() as u32).clamp(1, brush_size.max(1))
+}
+
+fn paint_interval(brush_size: u32) -> Duration {
+    let millis = MIN_PAINT_INTERVAL_MS + brush_size as u64 / 2;
+    Duration::from_millis(millis.min(MAX_PAINT_INTERVAL_MS))
+}
+
+fn make_bitmap_cursor(window: &WindowHandle, rows: &[&str], hot: (f64, f64)) -> Cursor {
+    let size = rows.len();
+    let mut pixels = vec![0u8; size * size * 4];
+    for (y, row) in rows.iter().enumerate() {
+        for (x, symbol) in row.chars().take(size).enumerate() {
+            let value = match symbol {
+                '#' => 0,
+                '.' => 255,
+                _ => continue,
+            };
+            let offset = (y * size + x) * 4;
+            pixels[offset..offset + 4].copy_from_slice(&[value, value, value, 255]);
+        }
+    }
+    let image = ImageBuf::from_raw(pixels, ImageFormat::RgbaSeparate, size, size);
+    window
+        .make_cursor(&CursorDesc::new(image, hot))
+        .unwrap_or(Cursor::Crosshair)
+}
+
+fn blend_pixel(dst: &mut Rgba<u8>, src: Rgba<u8>) {
+    let src_a = src[3] as f32 / 255.0;
+    let dst_a = dst[3] as f32 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    if out_a <= 0.0 {
+        *dst = Rgba([0, 0, 0, 0]);
+        return;
+    }
+    for channel in 0..3 {
+        let value = (src[channel] as f32 * src_a + dst[channel] as f32 * dst_a * (1.0 - src_a)) / out_a;
+        dst[channel] = value.round() as u8;
+    }
+    dst[3] = (out_a * 255.0).round() as u8;
+}
+
+fn mix_pixel(dst: &mut Rgba<u8>, target: Rgba<u8>, amount: f32) {
+    let dst_a = dst[3] as f32 / 255.0;
+    let target_a = target[3] as f32 / 255.0;
+    let out_a = dst_a * (1.0 - amount) + target_a * amount;
+    if out_a <= 0.0 {
+        *dst = Rgba([0, 0, 0, 0]);
+        return;
+    }
+    for channel in 0..3 {
+        let value = (dst[channel] as f32 * dst_a * (1.0 - amount)
+            + target[channel] as f32 * target_a * amount)
+            / out_a;
+        dst[channel] = value.round() as u8;
+    }
+    dst[3] = (out_a * 255.0).round() as u8;
+}
+
+fn select_tool(state: &mut AppState, tool: Tool) {
+    if state.current_tool == tool {
+        return;
+    }
+    let current = ToolSettings {
+        size: state.brush_size,
+        opacity: state.brush_opacity,
+        flow: state.brush_flow,
+        hardness: state.brush_hardness,
+    };
+    Arc::make_mut(&mut state.tool_settings).insert(state.current_tool.clone(), current);
+    if let Some(saved) = state.tool_settings.get(&tool).copied() {
+        state.brush_size = saved.size;
+        state.brush_size_input = saved.size.to_string();
+        state.brush_opacity = saved.opacity;
+        state.brush_flow = saved.flow;
+        state.brush_hardness = saved.hardness;
+    }
+    state.previous_tool = std::mem::replace(&mut state.current_tool, tool);
+}
+
+fn pick_color(state: &mut AppState, pos: Point) {
+    let image_pos = screen_to_image(state, pos);
+    let color = {
+        let merged;
+        let active;
+        let image: &RgbaImage = if state.picker_merged {
+            merged = composite_layers(&state.layers);
+            &merged
+        } else {
+            active = state.image.read().unwrap();
+            &active
+        };
+        let (width, height) = image.dimensions();
+        if image_pos.x < 0.0 || image_pos.y < 0.0 || image_pos.x >= width as f64 || image_pos.y >= height as f64 {
+            return;
+        }
+        let half = (state.picker_sample_size / 2) as i64;
+        let (x_center, y_center) = (image_pos.x as i64, image_pos.y as i64);
+        let mut sums = [0.0f64; 3];
+        let mut total_alpha = 0.0;
+        for y in (y_center - half).max(0)..=(y_center + half).min(height as i64 - 1) {
+            for x in (x_center - half).max(0)..=(x_center + half).min(width as i64 - 1) {
+                let pixel = image.get_pixel(x as u32, y as u32);
+                let alpha = pixel[3] as f64;
+                for (sum, &channel) in sums.iter_mut().zip(&pixel.0[..3]) {
+                    *sum += channel as f64 * alpha;
+                }
+                total_alpha += alpha;
+            }
+        }
+        if total_alpha == 0.0 {
+            state.status_message = StatusMessage::new("status-picked-transparent");
+            return;
+        }
+        let [r, g, b] = sums.map(|sum| (sum / total_alpha).round() as u8);
+        Color::rgb8(r, g, b)
+    };
+    set_brush_color(state, color);
+}
+
+fn set_brush_color(state: &mut AppState, color: Color) {
+    let (r, g, b, a) = color.as_rgba8();
+    state.brush_color = color;
+    remember_color(state, color);
+    state.color_r_input = r.to_string();
+    state.color_g_input = g.to_string();
+    state.color_b_input = b.to_string();
+    state.color_a_input = a.to_string();
+    state.color_hex_input = format_hex_color(color);
+}
+
+fn update_brush_color(state: &mut AppState) {
+    let r = state
+        .color_r_input
+        .parse::<u8>()
+        .unwrap_or(0)
+        .clamp(0, 255);
+    let g = state
+        .color_g_input
+        .parse::<u8>()
+        .unwrap_or(0)
+        .clamp(0, 255);
+    let b = state
+        .color_b_input
+        .parse::<u8>()
+        .unwrap_or(0)
+        .clamp(0, 255);
+    let a = state
+        .color_a_input
+        .trim()
+        .parse::<i64>()
+        .map_or(255, |alpha| alpha.clamp(0, 255) as u8);
+    state.brush_color = Color::rgba8(r, g, b, a);
+    state.color_hex_input = format_hex_color(state.brush_color);
+    remember_color(state, state.brush_color);
+}
+
+fn parse_hex_color(input: &str) -> Option<Color> {
+    let hex = input.trim().strip_prefix('#')?;
+    if !matches!(hex.len(), 6 | 8) || !hex.chars().all(|digit| digit.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Some(Color::rgba8(channel(0)?, channel(2)?, channel(4)?, alpha))
+}
+
+fn format_hex_color(color: Color) -> String {
+    let (r, g, b, a) = color.as_rgba8();
+    if a == 255 {
+        format!("#{:02X}{:02X}{:02X}", r, g, b)
+    } else {
+        format!("#{:02X}{:02X}{:02X}{:02X}", r, g, b, a)
+    }
+}
+
+fn remember_color(state: &mut AppState, color: Color) {
+    let recent = Arc::make_mut(&mut state.recent_colors);
+    recent.retain(|existing| existing.as_rgba8() != color.as_rgba8());
+    recent.insert(0, color);
+    recent.truncate(MAX_RECENT_COLORS);
+}
+
+fn push_undo(history: &mut Arc<History>, layer: &Arc<RwLock<RgbaImage>>, image: &RgbaImage) {
+    push_snapshot(
+        history,
+        Snapshot::Pixels {
+            layer: layer.clone(),
+            image: Arc::new(image.clone()),
+        },
+    );
+}
+
+fn mark_image_changed(state: &mut AppState) {
+    state.image_generation += 1;
+    state.dirty = true;
+}
+
+fn push_layers_undo(state: &mut AppState) {
+    state.dirty = true;
+    let snapshot = Snapshot::Layers {
+        layers: state.layers.clone(),
+        active_layer: state.active_layer,
+    };
+    push_snapshot(&mut state.history, snapshot);
+}
+
+fn push_snapshot(history: &mut Arc<History>, snapshot: Snapshot) {
+    let history = Arc::make_mut(history);
+    history.undo.push(snapshot);
+    if history.undo.len() > MAX_UNDO_STEPS {
+        history.undo.remove(0);
+    }
+    history.redo.clear();
+}
+
+fn undo(state: &mut AppState) {
+    let Some(snapshot) = Arc::make_mut(&mut state.history).undo.pop() else {
+        state.status_message = StatusMessage::new("status-nothing-to-undo");
+        return;
+    };
+    let current = restore_snapshot(state, snapshot);
+    Arc::make_mut(&mut state.history).redo.push(current);
+}
+
+fn redo(state: &mut AppState) {
+    let Some(snapshot) = Arc::make_mut(&mut state.history).redo.pop() else {
+        state.status_message = StatusMessage::new("status-nothing-to-redo");
+        return;
+    };
+    let current = restore_snapshot(state, snapshot);
+    Arc::make_mut(&mut state.history).undo.push(current);
+}
+
+fn restore_snapshot(state: &mut AppState, snapshot: Snapshot) -> Snapshot {
+    state.dirty = true;
+    match snapshot {
+        Snapshot::Pixels { layer, image } => {
+            let current = std::mem::replace(&mut *layer.write().unwrap(), (*image).clone());
+            mark_image_changed(state);
+            if let Some(index) = state.layers.iter().position(|candidate| Arc::ptr_eq(&candidate.image, &layer)) {
+                set_active_layer(state, index);
+            }
+            Snapshot::Pixels {
+                layer,
+                image: Arc::new(current),
+            }
+        }
+        Snapshot::Layers { layers, active_layer } => {
+            let current = Snapshot::Layers {
+                layers: std::mem::replace(&mut state.layers, layers),
+                active_layer: state.active_layer,
+            };
+            set_active_layer(state, active_layer);
+            current
+        }
+    }
+}
+
+fn set_active_layer(state: &mut AppState, index: usize) {
+    state.active_layer = index.min(state.layers.len() - 1);
+    state.image = state.layers[state.active_layer].image.clone();
+}
+
+fn replace_document(state: &mut AppState, image: RgbaImage) {
+    push_layers_undo(state);
+    state.layers = Arc::new(vec![Layer::new("Background".to_string(), image)]);
+    set_active_layer(state, 0);
+    state.selection = None;
+    state.floating = None;
+    state.polygon_points = Arc::new(Vec::new());
+    fit_to_view(state);
+    state.dirty = false;
+}
+
+fn add_layer(state: &mut AppState) {
+    let (width, height) = state.image.read().unwrap().dimensions();
+    push_layers_undo(state);
+    let name = format!("Layer {}", state.layers.len() + 1);
+    let index = state.active_layer + 1;
+    Arc::make_mut(&mut state.layers).insert(index, Layer::new(name, RgbaImage::new(width, height)));
+    set_active_layer(state, index);
+}
+
+fn delete_layer(state: &mut AppState) {
+    if state.layers.len() == 1 {
+        state.status_message = StatusMessage::new("status-only-layer");
+        return;
+    }
+    push_layers_undo(state);
+    Arc::make_mut(&mut state.layers).remove(state.active_layer);
+    set_active_layer(state, state.active_layer.saturating_sub(1));
+}
+
+fn move_layer(state: &mut AppState, up: bool) {
+    let index = state.active_layer;
+    let target = if up { index + 1 } else { index.wrapping_sub(1) };
+    if target >= state.layers.len() {
+        return;
+    }
+    push_layers_undo(state);
+    Arc::make_mut(&mut state.layers).swap(index, target);
+    set_active_layer(state, target);
+}
+
+fn blend_channel(mode: BlendMode, backdrop: f32, source: f32) -> f32 {
+    match mode {
+        BlendMode::Normal => source,
+        BlendMode::Multiply => backdrop * source,
+        BlendMode::Screen => backdrop + source - backdrop * source,
+        BlendMode::Overlay if backdrop <= 0.5 => 2.0 * backdrop * source,
+        BlendMode::Overlay => 1.0 - 2.0 * (1.0 - backdrop) * (1.0 - source),
+        BlendMode::Add => (backdrop + source).min(1.0),
+    }
+}
+
+fn composite_layers(layers: &[Layer]) -> RgbaImage {
+    let (width, height) = layers[0].image.read().unwrap().dimensions();
+    composite_region(layers, (0, 0, width, height), None)
+}
+
+// Composites the `(x, y, width, height)` region of the layer stack. An in-progress
+// stroke is blended into the layer at its index first, so it previews beneath any
+// layers stacked above that one.
+fn composite_region(
+    layers: &[Layer],
+    (x_origin, y_origin, width, height): (u32, u32, u32, u32),
+    stroke: Option<(usize, &RgbaImage)>,
+) -> RgbaImage {
+    let mut composite = RgbaImage::new(width, height);
+    for (index, layer) in layers.iter().enumerate().filter(|(_, layer)| layer.visible) {
+        let image = layer.image.read().unwrap();
+        let stroke_layer = stroke.and_then(|(stroke_index, stroke_layer)| (stroke_index == index).then_some(stroke_layer));
+        let row_span = |source: &RgbaImage, y: u32| {
+            let start = ((y_origin + y) * source.width() + x_origin) as usize * 4;
+            source.as_raw()[start..start + width as usize * 4].to_vec()
+        };
+        for y in 0..height {
+            let mut sources = row_span(&image, y);
+            if let Some(stroke_layer) = stroke_layer {
+                for (source, stroke_pixel) in sources.chunks_exact_mut(4).zip(row_span(stroke_layer, y).chunks_exact(4)) {
+                    if stroke_pixel[3] > 0 {
+                        blend_pixel(Rgba::from_slice_mut(source), *Rgba::from_slice(stroke_pixel));
+                    }
+                }
+            }
+            let row_start = (y * width) as usize * 4;
+            let pixels = &mut composite.as_mut()[row_start..row_start + width as usize * 4];
+            for (pixel, source) in pixels.chunks_exact_mut(4).zip(sources.chunks_exact(4)) {
+                let pixel = Rgba::from_slice_mut(pixel);
+                let alpha = (source[3] as f64 * layer.opacity).round() as u8;
+                if alpha == 0 {
+                    continue;
+                }
+                let mut color = [source[0], source[1], source[2]];
+                if layer.blend_mode != BlendMode::Normal && pixel[3] > 0 {
+                    let backdrop_alpha = pixel[3] as f32 / 255.0;
+                    for (channel, value) in color.iter_mut().enumerate() {
+                        let blended = blend_channel(layer.blend_mode, pixel[channel] as f32 / 255.0, *value as f32 / 255.0);
+                        let mixed = *value as f32 / 255.0 * (1.0 - backdrop_alpha) + blended * backdrop_alpha;
+                        *value = (mixed * 255.0).round() as u8;
+                    }
+                }
+                blend_pixel(pixel, Rgba([color[0], color[1], color[2], alpha]));
+            }
+        }
+    }
+    composite
+}
+
+// The composited pixels inside the selection's bounding box.
+fn selection_region(state: &AppState) -> Option<RgbaImage> {
+    let bounds = state.selection.as_ref()?.bounds?;
+    let image = composite_layers(&state.layers);
+    Some(
+        crop_imm(
+            &image,
+            bounds.x0 as u32,
+            bounds.y0 as u32,
+            bounds.width() as u32,
+            bounds.height() as u32,
+        )
+        .to_image(),
+    )
+}
+
+fn copy_selection(state: &mut AppState) -> bool {
+    let Some(selection) = state.selection.clone() else {
+        state.status_message = StatusMessage::new("status-nothing-selected");
+        return false;
+    };
+    let Some(bounds) = selection.bounds else {
+        return false;
+    };
+    let image = state.image.read().unwrap();
+    let (x0, y0) = (bounds.x0 as u32, bounds.y0 as u32);
+    let copied = RgbaImage::from_fn(bounds.width() as u32, bounds.height() as u32, |x, y| {
+        let mut pixel = *image.get_pixel(x0 + x, y0 + y);
+        let strength = selection.mask[((y0 + y) * image.width() + x0 + x) as usize];
+        pixel[3] = (pixel[3] as f32 * strength).round() as u8;
+        pixel
+    });
+    drop(image);
+    state.clipboard = Some(Arc::new(copied));
+    true
+}
+
+fn cut_selection(state: &mut AppState) {
+    if !copy_selection(state) {
+        return;
+    }
+    let Some(selection) = state.selection.clone() else {
+        return;
+    };
+    mark_image_changed(state);
+    let mut image = state.image.write().unwrap();
+    push_undo(&mut state.history, &state.image, &image);
+    for (pixel, strength) in image.pixels_mut().zip(&selection.mask) {
+        if *strength > 0.0 {
+            mix_pixel(pixel, Rgba([0, 0, 0, 0]), *strength);
+        }
+    }
+}
+
+// Unsaved work asks for confirmation first; a clean document is cleared straight away.
+fn request_clear_canvas(state: &mut AppState) {
+    if state.dirty {
+        state.clear_pending = true;
+    } else {
+        clear_canvas(state);
+    }
+}
+
+fn clear_canvas(state: &mut AppState) {
+    let (r, g, b, a) = state.background_color.as_rgba8();
+    mark_image_changed(state);
+    let mut image = state.image.write().unwrap();
+    push_undo(&mut state.history, &state.image, &image);
+    for pixel in image.pixels_mut() {
+        *pixel = Rgba([r, g, b, a]);
+    }
+}
+
+fn paste_floating(state: &mut AppState) {
+    commit_floating(state);
+    let Some(clipboard) = state.clipboard.clone() else {
+        return;
+    };
+    let (width, height) = state.image.read().unwrap().dimensions();
+    let center = state
+        .cursor_pos
+        .unwrap_or_else(|| Point::new(width as f64 / 2.0, height as f64 / 2.0));
+    state.floating_origin = Point::new(
+        (center.x - clipboard.width() as f64 / 2.0).round(),
+        (center.y - clipboard.height() as f64 / 2.0).round(),
+    );
+    state.floating = Some(clipboard);
+    state.selection = None;
+}
+
+fn commit_floating(state: &mut AppState) {
+    let Some(floating) = state.floating.take() else {
+        return;
+    };
+    mark_image_changed(state);
+    let mut image = state.image.write().unwrap();
+    push_undo(&mut state.history, &state.image, &image);
+    overlay(
+        &mut *image,
+        &*floating,
+        state.floating_origin.x.round() as i64,
+        state.floating_origin.y.round() as i64,
+    );
+}
+
+fn paste_from_clipboard(state: &mut AppState) {
+    let Some(bytes) = Application::global().clipboard().get_format("image/png") else {
+        state.status_message = StatusMessage::new("status-clipboard-no-image");
+        return;
+    };
+    match image::load_from_memory(&bytes) {
+        Ok(pasted) => {
+            paste_image(state, &pasted.to_rgba8());
+            state.status_message = StatusMessage::new("status-clipboard-pasted");
+        }
+        Err(err) => state.status_message = StatusMessage::new("status-clipboard-paste-failed").with_arg("error", err),
+    }
+}
+
+fn paste_image(state: &mut AppState, pasted: &RgbaImage) {
+    mark_image_changed(state);
+    let mut image = state.image.write().unwrap();
+    push_undo(&mut state.history, &state.image, &image);
+    let center = state
+        .cursor_pos
+        .unwrap_or_else(|| Point::new(image.width() as f64 / 2.0, image.height() as f64 / 2.0));
+    let x_origin = center.x.round() as i64 - pasted.width() as i64 / 2;
+    let y_origin = center.y.round() as i64 - pasted.height() as i64 / 2;
+    overlay(&mut *image, pasted, x_origin, y_origin);
+}
+
+fn parse_color_mapping(text: &str) -> Option<Vec<(Rgba<u8>, Rgba<u8>)>> {
+    let to_rgba = |color: Color| {
+        let (r, g, b, a) = color.as_rgba8();
+        Rgba([r, g, b, a])
+    };
+    let mapping = text
+        .split(';')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (from, to) = pair.split_once('=')?;
+            Some((to_rgba(parse_rgb(from)?), to_rgba(parse_rgb(to)?)))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (!mapping.is_empty()).then_some(mapping)
+}
+
+fn remap_colors(image: &mut RgbaImage, mapping: &[(Rgba<u8>, Rgba<u8>)]) {
+    let distance = |a: &Rgba<u8>, b: &Rgba<u8>| -> u32 {
+        (0..3).map(|channel| (a[channel] as i32 - b[channel] as i32).pow(2) as u32).sum()
+    };
+    for pixel in image.pixels_mut() {
+        if pixel[3] == 0 {
+            continue;
+        }
+        let Some((_, to)) = mapping.iter().min_by_key(|(from, _)| distance(pixel, from)) else {
+            return;
+        };
+        *pixel = Rgba([to[0], to[1], to[2], pixel[3]]);
+    }
+}
+
+fn fill_at(state: &mut AppState, pos: Point) {
+    let (width, height) = state.image.read().unwrap().dimensions();
+    let image_pos = screen_to_image(state, pos);
+    if image_pos.x < 0.0 || image_pos.y < 0.0 || image_pos.x >= width as f64 || image_pos.y >= height as f64 {
+        return;
+    }
+    mark_image_changed(state);
+    let composite = composite_layers(&state.layers);
+    let mut image = state.image.write().unwrap();
+    push_undo(&mut state.history, &state.image, &image);
+    let (r, g, b, a) = state.brush_color.as_rgba8();
+    flood_fill(
+        &mut image,
+        &composite,
+        image_pos.x as u32,
+        image_pos.y as u32,
+        Rgba([r, g, b, a]),
+        state.fill_tolerance,
+        state.fill_feather,
+    );
+}
+
+// The region is grown over `reference`, the merged composite, so the fill stops at
+// edges drawn on any visible layer; only `image`, the active layer, is written.
+fn flood_fill(
+    image: &mut RgbaImage,
+    reference: &RgbaImage,
+    x: u32,
+    y: u32,
+    color: Rgba<u8>,
+    tolerance: u32,
+    feather: u32,
+) {
+    let (width, height) = image.dimensions();
+    let target = *reference.get_pixel(x, y);
+    let matches = |pixel: &Rgba<u8>| {
+        let distance_squared: u32 = (0..3)
+            .map(|channel| (pixel[channel] as i32 - target[channel] as i32).pow(2) as u32)
+            .sum();
+        distance_squared <= tolerance * tolerance
+    };
+
+    let mut mask = vec![0.0f32; (width * height) as usize];
+    let mut pending = vec![(x, y)];
+    while let Some((x, y)) = pending.pop() {
+        let index = (y * width + x) as usize;
+        if mask[index] > 0.0 || !matches(reference.get_pixel(x, y)) {
+            continue;
+        }
+        mask[index] = 1.0;
+        if x > 0 {
+            pending.push((x - 1, y));
+        }
+        if x + 1 < width {
+            pending.push((x + 1, y));
+        }
+        if y > 0 {
+            pending.push((x, y - 1));
+        }
+        if y + 1 < height {
+            pending.push((x, y + 1));
+        }
+    }
+
+    if feather > 0 {
+        mask = box_blur_mask(&mask, width, height, feather);
+    }
+    for (pixel, coverage) in image.pixels_mut().zip(mask) {
+        if coverage > 0.0 {
+            let alpha = (color[3] as f32 * coverage).round() as u8;
+            blend_pixel(pixel, Rgba([color[0], color[1], color[2], alpha]));
+        }
+    }
+}
+
+fn filter_params(state: &AppState) -> FilterParams {
+    FilterParams {
+        blur_radius: state.blur_radius.round() as u32,
+        brightness: state.brightness.round() as i32,
+        contrast: state.contrast as f32,
+        threshold: state.threshold.round() as u8,
+        posterize_levels: state.posterize_levels.round() as u32,
+        hue: state.hue_shift.round() as f32,
+        saturation: state.saturation_shift.round() as f32,
+        lightness: state.lightness_shift.round() as f32,
+    }
+}
+
+fn composite_filter_preview(state: &AppState, image: &RgbaImage, index: usize, params: &FilterParams) -> RgbaImage {
+    let mut filtered = image.clone();
+    filters::registry(params).swap_remove(index).apply(&mut filtered);
+    let mut layers = (*state.layers).clone();
+    layers[state.active_layer].image = Arc::new(RwLock::new(filtered));
+    composite_layers(&layers)
+}
+
+fn apply_filter(state: &mut AppState, filter: Arc<dyn Filter>) {
+    mark_image_changed(state);
+    let mut image = state.image.write().unwrap();
+    push_undo(&mut state.history, &state.image, &image);
+    filter.apply(&mut image);
+    drop(image);
+    state.last_filter = Some(filter);
+}
+
+
+fn add_polygon_point(state: &mut AppState, pos: Point, click_count: u8) {
+    let mut points = (*state.polygon_points).clone();
+    let closes = points.len() >= 3
+        && (click_count >= 2
+            || image_to_screen(state, points[0]).distance(pos) <= POLYGON_CLOSE_DISTANCE);
+
+    if closes {
+        mark_image_changed(state);
+        let mut image = state.image.write().unwrap();
+        push_undo(&mut state.history, &state.image, &image);
+        let (r, g, b, a) = state.brush_color.as_rgba8();
+        fill_polygon(&mut image, &points, Rgba([r, g, b, a]));
+        points.clear();
+    } else if click_count < 2 {
+        points.push(screen_to_image(state, pos));
+    }
+    state.polygon_points = Arc::new(points);
+}
+
+fn fill_polygon(image: &mut RgbaImage, points: &[Point], color: Rgba<u8>) {
+    let (width, height) = image.dimensions();
+    let min_y = points.iter().map(|p| p.y).fold(f64::INFINITY, f64::min).floor().max(0.0) as u32;
+    let max_y = points.iter().map(|p| p.y).fold(f64::NEG_INFINITY, f64::max).ceil().min(height as f64) as u32;
+
+    for y in min_y..max_y {
+        let scan_y = y as f64 + 0.5;
+        let mut crossings = Vec::new();
+        for (index, start) in points.iter().enumerate() {
+            let end = points[(index + 1) % points.len()];
+            if (start.y <= scan_y) != (end.y <= scan_y) {
+                crossings.push(start.x + (scan_y - start.y) / (end.y - start.y) * (end.x - start.x));
+            }
+        }
+        crossings.sort_by(|a, b| a.total_cmp(b));
+
+        // Even-odd rule: pixel centers between each pair of crossings are inside.
+        for span in crossings.chunks_exact(2) {
+            let x_start = (span[0] - 0.5).ceil().clamp(0.0, width as f64) as u32;
+            let x_end = (span[1] - 0.5).ceil().clamp(0.0, width as f64) as u32;
+            for x in x_start..x_end {
+                blend_pixel(image.get_pixel_mut(x, y), color);
+            }
+        }
+    }
+}
+
+fn begin_stroke(state: &mut AppState) {
+    match state.current_tool {
+        Tool::Brush => {
+            let (width, height) = state.image.read().unwrap().dimensions();
+            state.stroke = Some(Arc::new(Mutex::new(StrokeBuffer::new(width, height))));
+        }
+        Tool::Eraser => {
+            let base = state.image.read().unwrap().clone();
+            let coverage = vec![0.0; (base.width() * base.height()) as usize];
+            state.erase_stroke = Some(Arc::new(Mutex::new(EraseBuffer { base, coverage })));
+        }
+        Tool::Smudge => state.smudge_carry = Some(Arc::new(Mutex::new(RgbaImage::new(0, 0)))),
+        _ => {}
+    }
+}
+
+fn commit_stroke(state: &mut AppState) {
+    state.smudge_carry = None;
+    state.erase_stroke = None;
+    if let Some(stroke) = state.stroke.take() {
+        mark_image_changed(state);
+        stroke.lock().unwrap().composite(&mut state.image.write().unwrap());
+    }
+}
+
+fn stroke_thickness(state: &AppState) -> f64 {
+    (state.brush_size * 2 + 1) as f64
+}
+
+fn constrain_square(start: Point, end: Point) -> Point {
+    let delta = end - start;
+    let side = delta.x.abs().min(delta.y.abs());
+    start + Vec2::new(side.copysign(delta.x), side.copysign(delta.y))
+}
+
+fn draw_shape(state: &mut AppState, bounds: Rect, ellipse: bool) {
+    mark_image_changed(state);
+    let mut image = state.image.write().unwrap();
+    push_undo(&mut state.history, &state.image, &image);
+    let (width, height) = image.dimensions();
+    let thickness = stroke_thickness(state);
+    let inner = bounds.inset(-thickness);
+    let inside = |rect: Rect, point: Point| {
+        if !ellipse {
+            return rect.contains(point);
+        }
+        let (rx, ry) = (rect.width() / 2.0, rect.height() / 2.0);
+        if rx <= 0.0 || ry <= 0.0 {
+            return false;
+        }
+        let center = rect.center();
+        ((point.x - center.x) / rx).powi(2) + ((point.y - center.y) / ry).powi(2) <= 1.0
+    };
+    let mut color = tool_color(state);
+    color[3] = (color[3] as f64 * state.brush_opacity).round() as u8;
+
+    let clipped = bounds.round().intersect(Rect::new(0.0, 0.0, width as f64, height as f64));
+    for y in clipped.y0 as u32..clipped.y1 as u32 {
+        for x in clipped.x0 as u32..clipped.x1 as u32 {
+            let point = Point::new(x as f64 + 0.5, y as f64 + 0.5);
+            if inside(bounds, point) && (state.shape_fill || !inside(inner, point)) {
+                blend_pixel(image.get_pixel_mut(x, y), color);
+            }
+        }
+    }
+}
+
+fn draw_line(state: &mut AppState, start: Point, end: Point) {
+    mark_image_changed(state);
+    let mut image = state.image.write().unwrap();
+    push_undo(&mut state.history, &state.image, &image);
+    let (width, height) = image.dimensions();
+    let mut stroke = StrokeBuffer::new(width, height);
+    let color = tool_color(state);
+    let spacing = (state.brush_size as f64 / 2.0).max(1.0);
+    let steps = (start.distance(end) / spacing).ceil().max(1.0) as usize;
+    for step in 0..=steps {
+        let point = start.lerp(end, step as f64 / steps as f64);
+        stamp_brush(
+            &mut stroke.layer,
+            state,
+            StampMode::Deposit,
+            point.x as i32,
+            point.y as i32,
+            color,
+            Some(&mut stroke.coverage),
+        );
+    }
+    stroke.composite(&mut image);
+}
+
+fn fill_gradient(state: &mut AppState, start: Point, end: Point) {
+    let to_rgba = |color: Color| {
+        let (r, g, b, a) = color.as_rgba8();
+        Rgba([r, g, b, a])
+    };
+    let (start_color, end_color) = (to_rgba(state.brush_color), to_rgba(state.gradient_end_color));
+    mark_image_changed(state);
+    let mut image = state.image.write().unwrap();
+    push_undo(&mut state.history, &state.image, &image);
+    let mask = state.selection.as_ref().map(|selection| selection.mask.as_slice());
+    match state.gradient_mode {
+        GradientMode::Linear => fill_linear_gradient(&mut image, start, end, start_color, end_color, mask),
+        GradientMode::Radial => fill_radial_gradient(&mut image, start, end, start_color, end_color, mask),
+    }
+}
+
+fn fill_linear_gradient(
+    image: &mut RgbaImage,
+    start: Point,
+    end: Point,
+    start_color: Rgba<u8>,
+    end_color: Rgba<u8>,
+    mask: Option<&[f32]>,
+) {
+    let axis = end - start;
+    let length_squared = axis.hypot2().max(f64::EPSILON);
+    paint_gradient(image, start_color, end_color, mask, |point| {
+        (point - start).dot(axis) / length_squared
+    });
+}
+
+fn fill_radial_gradient(
+    image: &mut RgbaImage,
+    center: Point,
+    edge: Point,
+    start_color: Rgba<u8>,
+    end_color: Rgba<u8>,
+    mask: Option<&[f32]>,
+) {
+    let radius = center.distance(edge).max(f64::EPSILON);
+    paint_gradient(image, start_color, end_color, mask, |point| point.distance(center) / radius);
+}
+
+fn paint_gradient(
+    image: &mut RgbaImage,
+    start_color: Rgba<u8>,
+    end_color: Rgba<u8>,
+    mask: Option<&[f32]>,
+    position: impl Fn(Point) -> f64,
+) {
+    let width = image.width();
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let strength = mask.map_or(1.0, |mask| mask[(y * width + x) as usize]);
+        if strength <= 0.0 {
+            continue;
+        }
+        let t = position(Point::new(x as f64 + 0.5, y as f64 + 0.5)).clamp(0.0, 1.0);
+        let mut color = Rgba([0; 4]);
+        for channel in 0..4 {
+            let value = start_color[channel] as f64 + (end_color[channel] as f64 - start_color[channel] as f64) * t;
+            color[channel] = value.round() as u8;
+        }
+        color[3] = (color[3] as f32 * strength).round() as u8;
+        blend_pixel(pixel, color);
+    }
+}
+
+fn draw_stroke_segment(state: &mut AppState, from: Point, to: Point, ctx: &mut EventCtx) {
+    let positions = stroke_positions(state, from, to);
+    draw_on_canvas(state, &positions, ctx);
+}
+
+// Dabs are at most half a brush apart (one image pixel for the pencil), measured in
+// screen space; `from` was stamped by the previous segment and is left out.
+fn stroke_positions(state: &AppState, from: Point, to: Point) -> Vec<Point> {
+    let spacing = if state.current_tool == Tool::Pencil {
+        state.zoom
+    } else {
+        (state.brush_size as f64 / 2.0).max(1.0) * state.zoom
+    };
+    let steps = (from.distance(to) / spacing).ceil().max(1.0) as usize;
+    (1..=steps)
+        .map(|step| from.lerp(to, step as f64 / steps as f64))
+        .collect()
+}
+
+// Every dab of a batch is stamped under a single lock so a fast segment costs one
+// write lock instead of one per dab.
+fn draw_on_canvas(state: &mut AppState, positions: &[Point], ctx: &mut EventCtx) {
+    let Some(image_dirty) = stamp_positions(state, positions) else {
+        return;
+    };
+    let dirty_rect = Rect::from_points(
+        image_to_screen(state, image_dirty.origin()),
+        image_to_screen(state, Point::new(image_dirty.x1, image_dirty.y1)),
+    );
+    if state.show_dirty_rect {
+        ctx.request_paint();
+    } else {
+        ctx.request_paint_rect(dirty_rect);
+    }
+}
+
+// Stamps a batch of screen positions and returns the image-space rect they touched.
+fn stamp_positions(state: &mut AppState, positions: &[Point]) -> Option<Rect> {
+    if positions.is_empty() {
+        return None;
+    }
+    // An antialiased circle reaches one pixel past its radius.
+    let radius = if state.current_tool == Tool::Pencil {
+        0
+    } else if state.rotate_to_direction {
+        (state.brush_size as f64 * std::f64::consts::SQRT_2).ceil() as i32 + state.brush_antialiasing as i32
+    } else {
+        state.brush_size as i32 + state.brush_antialiasing as i32
+    };
+    let color = tool_color(state);
+    let mode = stamp_mode(&state.current_tool);
+    let centers: Vec<(i32, i32)> = positions
+        .iter()
+        .map(|&pos| {
+            let image_pos = screen_to_image(state, pos);
+            (image_pos.x as i32, image_pos.y as i32)
+        })
+        .collect();
+    let (width, height) = state.image.read().unwrap().dimensions();
+    let centers = mirror_centers(centers, state.symmetry, width, height);
+    match (&state.stroke, state.erase_stroke.clone()) {
+        (Some(stroke), _) => {
+            let mut stroke = stroke.lock().unwrap();
+            let StrokeBuffer { layer, coverage } = &mut *stroke;
+            for &(x_center, y_center) in &centers {
+                stamp_brush(layer, state, mode, x_center, y_center, color, Some(coverage));
+            }
+        }
+        (None, Some(erase)) => {
+            mark_image_changed(state);
+            let mut erase = erase.lock().unwrap();
+            let mut image = state.image.write().unwrap();
+            for &(x_center, y_center) in &centers {
+                erase_brush(&mut image, &mut erase, state, x_center, y_center, color);
+            }
+        }
+        (None, None) => {
+            mark_image_changed(state);
+            let mut image = state.image.write().unwrap();
+            for &(x_center, y_center) in &centers {
+                stamp_brush(&mut image, state, mode, x_center, y_center, color, None);
+            }
+        }
+    }
+
+    let image_dirty = centers
+        .iter()
+        .map(|&(x, y)| {
+            Rect::new(
+                (x - radius) as f64,
+                (y - radius) as f64,
+                (x + radius + 1) as f64,
+                (y + radius + 1) as f64,
+            )
+        })
+        .reduce(|dirty, dab| dirty.union(dab))?;
+    state.stroke_dirty = Some(match state.stroke_dirty {
+        Some(dirty) => dirty.union(image_dirty),
+        None => image_dirty,
+    });
+    Some(image_dirty)
+}
+
+fn mirror_centers(centers: Vec<(i32, i32)>, symmetry: Symmetry, width: u32, height: u32) -> Vec<(i32, i32)> {
+    let mirror_x = |x: i32| width as i32 - 1 - x;
+    let mirror_y = |y: i32| height as i32 - 1 - y;
+    match symmetry {
+        Symmetry::None => centers,
+        Symmetry::Vertical => centers.iter().flat_map(|&(x, y)| [(x, y), (mirror_x(x), y)]).collect(),
+        Symmetry::Horizontal => centers.iter().flat_map(|&(x, y)| [(x, y), (x, mirror_y(y))]).collect(),
+        Symmetry::Both => centers
+            .iter()
+            .flat_map(|&(x, y)| [(x, y), (mirror_x(x), y), (x, mirror_y(y)), (mirror_x(x), mirror_y(y))])
+            .collect(),
+    }
+}
+
+fn spray_at(state: &mut AppState, pos: Point, rng: &mut StdRng) {
+    let center = screen_to_image(state, pos);
+    let radius = state.brush_size.max(1) as f64;
+    let (r, g, b, a) = state.brush_color.as_rgba8();
+    let color = Rgba([r, g, b, (a as f64 * state.brush_opacity).round() as u8]);
+    mark_image_changed(state);
+    let mut image = state.image.write().unwrap();
+    let (width, height) = image.dimensions();
+    for _ in 0..state.spray_density.round() as u32 {
+        let distance = radius * rng.r#gen::<f64>().sqrt();
+        let angle = rng.gen_range(0.0..std::f64::consts::TAU);
+        let x = (center.x + distance * angle.cos()).floor();
+        let y = (center.y + distance * angle.sin()).floor();
+        if x >= 0.0 && y >= 0.0 && x < width as f64 && y < height as f64 {
+            blend_pixel(image.get_pixel_mut(x as u32, y as u32), color);
+        }
+    }
+}
+
+fn tool_color(state: &AppState) -> Rgba<u8> {
+    match state.current_tool {
+        Tool::Brush
+        | Tool::Polygon
+        | Tool::Fill
+        | Tool::Select
+        | Tool::Line
+        | Tool::Rectangle
+        | Tool::Ellipse
+        | Tool::Picker
+        | Tool::Smudge
+        | Tool::Crop
+        | Tool::Spray
+        | Tool::Gradient
+        | Tool::Pencil => {
+            let (r, g, b, a) = state.brush_color.as_rgba8();
+            Rgba([r, g, b, a])
+        }
+        Tool::Eraser => {
+            let (r, g, b, a) = match state.erase_mode {
+                EraseMode::Transparent => (0, 0, 0, 0),
+                EraseMode::Background => state.background_color.as_rgba8(),
+                EraseMode::Secondary => state.secondary_color.as_rgba8(),
+            };
+            Rgba([r, g, b, a])
+        }
+    }
+}
+
+fn tool_for_key(code: Code) -> Option<Tool> {
+    match code {
+        Code::KeyB => Some(Tool::Brush),
+        Code::KeyI => Some(Tool::Picker),
+        Code::KeyG => Some(Tool::Fill),
+        Code::KeyP => Some(Tool::Polygon),
+        Code::KeyM => Some(Tool::Select),
+        Code::KeyU => Some(Tool::Line),
+        Code::KeyR => Some(Tool::Rectangle),
+        Code::KeyO => Some(Tool::Ellipse),
+        Code::KeyS => Some(Tool::Smudge),
+        Code::KeyC => Some(Tool::Crop),
+        Code::KeyA => Some(Tool::Spray),
+        Code::KeyN => Some(Tool::Pencil),
+        _ => None,
+    }
+}
+
+fn stamp_mode(tool: &Tool) -> StampMode {
+    match tool {
+        Tool::Eraser => StampMode::Remove,
+        Tool::Smudge => StampMode::Smudge,
+        _ => StampMode::Deposit,
+    }
+}
+
+fn canvas_cache_key(state: &AppState) -> CanvasCacheKey {
+    let preview = state.filter_preview.map(|index| (index, filter_params(state)));
+    (state.layers.clone(), state.image_generation, preview)
+}
+
+// The composited canvas image is only rebuilt when the layer stack, its pixels or the filter preview change.
+fn canvas_cache_is_current(cached: &CanvasCacheKey, current: &CanvasCacheKey) -> bool {
+    Arc::ptr_eq(&cached.0, &current.0) && cached.1 == current.1 && cached.2 == current.2
+}
+
+fn brush_preview_key(state: &AppState) -> BrushPreviewKey {
+    BrushPreviewKey {
+        pencil: state.current_tool == Tool::Pencil,
+        radius: state.brush_size.min(BRUSH_PREVIEW_RADIUS),
+        color: state.brush_color,
+        antialiasing: state.brush_antialiasing,
+        opacity: state.brush_opacity,
+        flow: state.brush_flow,
+        hardness: state.brush_hardness,
+        shape: state.brush_shape.clone(),
+        stamp: state.brush_stamp.clone(),
+        stamp_interp: state.stamp_interp.clone(),
+        angle: state.brush_angle,
+        rotate_to_direction: state.rotate_to_direction,
+        falloff_curve: state.falloff_curve,
+    }
+}
+
+// A single deposited dab with its radius capped at BRUSH_PREVIEW_RADIUS, however large the brush is.
+fn brush_preview_image(state: &AppState) -> RgbaImage {
+    let mut preview_state = state.clone();
+    preview_state.brush_size = state.brush_size.min(BRUSH_PREVIEW_RADIUS);
+    let diameter = preview_state.brush_size * 2 + 1;
+    let center = (diameter / 2) as i32;
+    let mut preview = RgbaImage::new(diameter, diameter);
+    let (r, g, b, a) = state.brush_color.as_rgba8();
+    let mut coverage = vec![0.0; (diameter * diameter) as usize];
+    stamp_brush(
+        &mut preview,
+        &preview_state,
+        StampMode::Deposit,
+        center,
+        center,
+        Rgba([r, g, b, a]),
+        Some(&mut coverage),
+    );
+    preview
+}
+
+fn stamp_brush(
+    image: &mut RgbaImage,
+    state: &AppState,
+    mode: StampMode,
+    x_center: i32,
+    y_center: i32,
+    color: Rgba<u8>,
+    mut stroke_coverage: Option<&mut Vec<f32>>,
+) {
+    let Some(dab) = brush_dab(state, x_center, y_center) else {
+        return;
+    };
+    let (width, height) = image.dimensions();
+    let x_start = (-dab.x_origin).clamp(0, dab.width as i32) as u32;
+    let x_end = (width as i32 - dab.x_origin).clamp(0, dab.width as i32) as u32;
+    let y_start = (-dab.y_origin).clamp(0, dab.height as i32) as u32;
+    let y_end = (height as i32 - dab.y_origin).clamp(0, dab.height as i32) as u32;
+    if x_start >= x_end || y_start >= y_end {
+        return;
+    }
+    let mut carry = match mode {
+        StampMode::Smudge => state.smudge_carry.as_ref().map(|carry| carry.lock().unwrap()),
+        _ => None,
+    };
+    if let Some(carry) = carry.as_deref_mut()
+        && carry.dimensions() != (dab.width, dab.height)
+    {
+        *carry = RgbaImage::from_fn(dab.width, dab.height, |dab_x, dab_y| {
+            let x = dab.x_origin + dab_x as i32;
+            let y = dab.y_origin + dab_y as i32;
+            if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                Rgba([0, 0, 0, 0])
+            } else {
+                *image.get_pixel(x as u32, y as u32)
+            }
+        });
+        return;
+    }
+    let span_length = (x_end - x_start) as usize;
+    let opaque_fill = matches!(mode, StampMode::Deposit)
+        && dab.colors.is_none()
+        && stroke_coverage.is_none()
+        && color[3] == 255;
+    let pixels: &mut [u8] = image.as_mut();
+
+    for dab_y in y_start..y_end {
+        let y = (dab.y_origin + dab_y as i32) as u32;
+        let x_first = (dab.x_origin + x_start as i32) as u32;
+        let row_start = (y * width + x_first) as usize;
+        let span = &mut pixels[row_start * 4..(row_start + span_length) * 4];
+        let coverage_start = (dab_y * dab.width + x_start) as usize;
+        let coverage_span = &dab.coverage[coverage_start..coverage_start + span_length];
+        if opaque_fill && coverage_span.iter().all(|&coverage| coverage >= 1.0) {
+            span.chunks_exact_mut(4).for_each(|pixel| pixel.copy_from_slice(&color.0));
+            continue;
+        }
+
+        for (offset, (pixel, &coverage)) in span.chunks_exact_mut(4).zip(coverage_span).enumerate() {
+            if coverage <= 0.0 {
+                continue;
+            }
+            let pixel = Rgba::from_slice_mut(pixel);
+            let dab_x = x_start + offset as u32;
+            match mode {
+                StampMode::Remove => {
+                    mix_pixel(pixel, color, coverage);
+                    continue;
+                }
+                StampMode::Smudge => {
+                    if let Some(carry) = carry.as_deref_mut() {
+                        let carried = carry.get_pixel_mut(dab_x, dab_y);
+                        mix_pixel(pixel, *carried, coverage * state.brush_opacity as f32);
+                        *carried = *pixel;
+                    }
+                    continue;
+                }
+                StampMode::Deposit => {}
+            }
+
+            let source = match &dab.colors {
+                Some(colors) => {
+                    let stamp_pixel = colors.get_pixel(dab_x, dab_y);
+                    Rgba([stamp_pixel[0], stamp_pixel[1], stamp_pixel[2], 255])
+                }
+                None => color,
+            };
+            match stroke_coverage.as_deref_mut() {
+                Some(stroke_coverage) => {
+                    let accumulated = &mut stroke_coverage[row_start + offset];
+                    *accumulated = (*accumulated + state.brush_flow as f32 * coverage * (1.0 - *accumulated))
+                        .min(state.brush_opacity as f32);
+                    let alpha = (source[3] as f32 * *accumulated).round() as u8;
+                    *pixel = Rgba([source[0], source[1], source[2], alpha]);
+                }
+                None if source[3] == 255 && coverage >= 1.0 => *pixel = source,
+                None => {
+                    let alpha = (source[3] as f32 * coverage).round() as u8;
+                    blend_pixel(pixel, Rgba([source[0], source[1], source[2], alpha]));
+                }
+            }
+        }
+    }
+}
+
+// Coverage accumulates per stroke, capped at the eraser opacity, and each pixel is
+// recomputed from the pre-stroke image so overlapping dabs do not compound.
+fn erase_brush(
+    image: &mut RgbaImage,
+    buffer: &mut EraseBuffer,
+    state: &AppState,
+    x_center: i32,
+    y_center: i32,
+    color: Rgba<u8>,
+) {
+    let Some(dab) = brush_dab(state, x_center, y_center) else {
+        return;
+    };
+    let (width, height) = image.dimensions();
+    for dab_y in 0..dab.height {
+        for dab_x in 0..dab.width {
+            let coverage = dab.coverage[(dab_y * dab.width + dab_x) as usize];
+            let (x, y) = (dab.x_origin + dab_x as i32, dab.y_origin + dab_y as i32);
+            if coverage <= 0.0 || x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                continue;
+            }
+            let index = (y as u32 * width + x as u32) as usize;
+            let accumulated = &mut buffer.coverage[index];
+            *accumulated = (*accumulated + state.brush_flow as f32 * coverage * (1.0 - *accumulated))
+                .min(state.brush_opacity as f32);
+            let mut pixel = *buffer.base.get_pixel(x as u32, y as u32);
+            mix_pixel(&mut pixel, color, *accumulated);
+            image.put_pixel(x as u32, y as u32, pixel);
+        }
+    }
+}
+
+fn brush_dab(state: &AppState, x_center: i32, y_center: i32) -> Option<Dab> {
+    if state.current_tool == Tool::Pencil {
+        return Some(Dab {
+            x_origin: x_center,
+            y_origin: y_center,
+            width: 1,
+            height: 1,
+            coverage: Arc::new(vec![1.0]),
+            colors: None,
+        });
+    }
+    let mut dab = shape_dab(state, x_center, y_center)?;
+    if state.rotate_to_direction && state.brush_shape != BrushShape::Circle && state.brush_angle != 0.0 {
+        dab = rotate_dab(&dab, state.brush_angle);
+    }
+    if state.falloff_curve != FalloffCurve::Linear {
+        for coverage in Arc::make_mut(&mut dab.coverage) {
+            *coverage = apply_falloff(state.falloff_curve, *coverage);
+        }
+    }
+    Some(dab)
+}
+
+fn apply_falloff(curve: FalloffCurve, coverage: f32) -> f32 {
+    match curve {
+        FalloffCurve::Linear => coverage,
+        FalloffCurve::EaseIn => coverage * coverage,
+        FalloffCurve::EaseOut => 1.0 - (1.0 - coverage) * (1.0 - coverage),
+    }
+}
+
+fn circle_mask(radius: i32, antialiasing: bool, hardness: f32) -> Arc<Vec<f32>> {
+    let key = (radius, antialiasing, hardness.to_bits());
+    let mut cache = CIRCLE_MASK_CACHE.lock().unwrap();
+    if let Some((cached_key, mask)) = cache.as_ref()
+        && *cached_key == key
+    {
+        return mask.clone();
+    }
+
+    let reach = radius + antialiasing as i32;
+    let side = reach * 2 + 1;
+    let mut mask = Vec::with_capacity(side as usize * side as usize);
+    for dy in -reach..=reach {
+        for dx in -reach..=reach {
+            let distance = ((dx * dx + dy * dy) as f32).sqrt();
+            let edge = if antialiasing {
+                (radius as f32 + 0.5 - distance).clamp(0.0, 1.0)
+            } else if dx * dx + dy * dy <= radius * radius {
+                1.0
+            } else {
+                0.0
+            };
+            let softness = if hardness >= 1.0 || radius == 0 {
+                1.0
+            } else {
+                ((1.0 - distance / radius as f32) / (1.0 - hardness)).clamp(0.0, 1.0)
+            };
+            mask.push(edge * softness);
+        }
+    }
+    let mask = Arc::new(mask);
+    *cache = Some((key, mask.clone()));
+    mask
+}
+
+fn square_mask(side: u32) -> Arc<Vec<f32>> {
+    let mut cache = SQUARE_MASK_CACHE.lock().unwrap();
+    if let Some((cached_side, mask)) = cache.as_ref()
+        && *cached_side == side
+    {
+        return mask.clone();
+    }
+    let mask = Arc::new(vec![1.0; side as usize * side as usize]);
+    *cache = Some((side, mask.clone()));
+    mask
+}
+
+fn scaled_stamp(stamp: &Arc<RgbaImage>, radius: i32, interp: &StampInterp) -> ScaledStamp {
+    let mut cache = STAMP_MASK_CACHE.lock().unwrap();
+    if let Some(((cached_radius, cached_interp, cached_stamp), scaled)) = cache.as_ref()
+        && *cached_radius == radius
+        && cached_interp == interp
+        && Arc::ptr_eq(cached_stamp, stamp)
+    {
+        return scaled.clone();
+    }
+
+    let diameter = (radius * 2 + 1) as f64;
+    let scale = diameter / stamp.width().max(stamp.height()) as f64;
+    let filter = match interp {
+        StampInterp::Nearest => FilterType::Nearest,
+        StampInterp::Bilinear => FilterType::Triangle,
+    };
+    let scaled = resize(
+        &**stamp,
+        ((stamp.width() as f64 * scale).round() as u32).max(1),
+        ((stamp.height() as f64 * scale).round() as u32).max(1),
+        filter,
+    );
+    let coverage = Arc::new(scaled.pixels().map(|pixel| pixel[3] as f32 / 255.0).collect());
+    let scaled = (Arc::new(scaled), coverage);
+    *cache = Some(((radius, interp.clone(), stamp.clone()), scaled.clone()));
+    scaled
+}
+
+fn rotate_dab(dab: &Dab, angle: f64) -> Dab {
+    let (sin, cos) = angle.sin_cos();
+    let (width, height) = (dab.width as f64, dab.height as f64);
+    let rotated_width = (width * cos.abs() + height * sin.abs()).ceil() as u32;
+    let rotated_height = (width * sin.abs() + height * cos.abs()).ceil() as u32;
+    let mut coverage = vec![0.0; (rotated_width * rotated_height) as usize];
+    let mut colors = dab.colors.as_ref().map(|_| RgbaImage::new(rotated_width, rotated_height));
+
+    for y in 0..rotated_height {
+        for x in 0..rotated_width {
+            let dx = x as f64 + 0.5 - rotated_width as f64 / 2.0;
+            let dy = y as f64 + 0.5 - rotated_height as f64 / 2.0;
+            let source_x = (dx * cos + dy * sin + width / 2.0).floor();
+            let source_y = (dy * cos - dx * sin + height / 2.0).floor();
+            if source_x < 0.0 || source_y < 0.0 || source_x >= width || source_y >= height {
+                continue;
+            }
+            let (source_x, source_y) = (source_x as u32, source_y as u32);
+            coverage[(y * rotated_width + x) as usize] = dab.coverage[(source_y * dab.width + source_x) as usize];
+            if let (Some(colors), Some(source)) = (colors.as_mut(), dab.colors.as_ref()) {
+                colors.put_pixel(x, y, *source.get_pixel(source_x, source_y));
+            }
+        }
+    }
+
+    Dab {
+        x_origin: dab.x_origin + (dab.width as i32 - rotated_width as i32) / 2,
+        y_origin: dab.y_origin + (dab.height as i32 - rotated_height as i32) / 2,
+        width: rotated_width,
+        height: rotated_height,
+        coverage: Arc::new(coverage),
+        colors: colors.map(Arc::new),
+    }
+}
+
+fn shape_dab(state: &AppState, x_center: i32, y_center: i32) -> Option<Dab> {
+    let radius = state.brush_size as i32;
+
+    match state.brush_shape {
+        BrushShape::Square => {
+            let side = (radius * 2 + 1) as u32;
+            Some(Dab {
+                x_origin: x_center - radius,
+                y_origin: y_center - radius,
+                width: side,
+                height: side,
+                coverage: square_mask(side),
+                colors: None,
+            })
+        }
+        BrushShape::Circle => {
+            let reach = radius + state.brush_antialiasing as i32;
+            let side = (reach * 2 + 1) as u32;
+            Some(Dab {
+                x_origin: x_center - reach,
+                y_origin: y_center - reach,
+                width: side,
+                height: side,
+                coverage: circle_mask(radius, state.brush_antialiasing, state.brush_hardness as f32),
+                colors: None,
+            })
+        }
+        BrushShape::Stamp => {
+            let (scaled, coverage) = scaled_stamp(state.brush_stamp.as_ref()?, radius, &state.stamp_interp);
+            Some(Dab {
+                x_origin: x_center - scaled.width() as i32 / 2,
+                y_origin: y_center - scaled.height() as i32 / 2,
+                width: scaled.width(),
+                height: scaled.height(),
+                coverage,
+                colors: Some(scaled),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    fn test_state(width: u32, height: u32) -> AppState {
+        let image = RgbaImage::from_pixel(width, height, WHITE);
+        AppState::new(Arc::new(RwLock::new(image)), Settings::default())
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("photoshop-mvp-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn flood_fill_stays_inside_a_ring() {
+        let mut image = RgbaImage::from_pixel(9, 9, WHITE);
+        let on_ring = |x: u32, y: u32| (x as i32 - 4).abs().max((y as i32 - 4).abs()) == 3;
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            if on_ring(x, y) {
+                *pixel = BLACK;
+            }
+        }
+        let reference = image.clone();
 
-fn set_background_color(state: &mut AppState, color: Color) {
-    state.background_color = color.clone();
-    let mut image = state.image.write().unwrap();
-    let (r, g, b, a) = color.as_rgba8();
-    for pixel in image.pixels_mut() {
-        *pixel = Rgba([r, g, b, a]);
+        flood_fill(&mut image, &reference, 4, 4, RED, 0, 0);
+
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let distance = (x as i32 - 4).abs().max((y as i32 - 4).abs());
+            let expected = match distance {
+                0..=2 => RED,
+                3 => BLACK,
+                _ => WHITE,
+            };
+            assert_eq!(*pixel, expected, "pixel ({}, {})", x, y);
+        }
     }
-}
 
-fn update_brush_color(state: &mut AppState) {
-    let r = state
-        .color_r_input
-        .parse::<u8>()
-        .unwrap_or(0)
-        .clamp(0, 255);
-    let g = state
-        .color_g_input
-        .parse::<u8>()
-        .unwrap_or(0)
-        .clamp(0, 255);
-    let b = state
-        .color_b_input
-        .parse::<u8>()
-        .unwrap_or(0)
-        .clamp(0, 255);
-    state.brush_color = Color::rgb8(r, g, b);
-}
+    #[test]
+    fn flood_fill_tolerance_is_an_rgb_distance() {
+        let mut image = RgbaImage::from_pixel(4, 1, WHITE);
+        image.put_pixel(2, 0, Rgba([255, 255, 255, 0]));
+        image.put_pixel(3, 0, Rgba([250, 255, 255, 255]));
 
-fn draw_on_canvas(state: &mut AppState, pos: Point, ctx: &mut EventCtx) {
-    let mut image = state.image.write().unwrap();
-    let x_center = (pos.x * image.width() as f64 / 800.0) as i32;
-    let y_center = (pos.y * image.height() as f64 / 600.0) as i32;
-    let radius = state.brush_size as i32;
+        let reference = image.clone();
+        flood_fill(&mut image, &reference, 0, 0, RED, 4, 0);
 
-    let color = match state.current_tool {
-        Tool::Brush => {
-            let (r, g, b, a) = state.brush_color.as_rgba8();
-            Rgba([r, g, b, a])
+        assert_eq!(*image.get_pixel(1, 0), RED);
+        assert_eq!(*image.get_pixel(2, 0), RED, "alpha does not count towards the distance");
+        assert_eq!(*image.get_pixel(3, 0), Rgba([250, 255, 255, 255]));
+    }
+
+    #[test]
+    fn open_image_reads_a_saved_png() {
+        let path = temp_path("open.png");
+        let mut image = RgbaImage::from_pixel(5, 3, WHITE);
+        image.put_pixel(3, 1, Rgba([10, 20, 30, 40]));
+        image.save(&path).unwrap();
+
+        let (opened, error) = open_image(&path).unwrap();
+        assert!(error.is_none());
+        assert_eq!(opened.dimensions(), (5, 3));
+        assert_eq!(*opened.get_pixel(3, 1), Rgba([10, 20, 30, 40]));
+
+        let partial = decode_partial_image(&path).unwrap();
+        assert_eq!(partial.dimensions(), (5, 3));
+        assert_eq!(*partial.get_pixel(3, 1), Rgba([10, 20, 30, 40]));
+    }
+
+    #[test]
+    fn save_image_round_trips_by_extension() {
+        let mut image = RgbaImage::from_pixel(6, 4, WHITE);
+        image.put_pixel(2, 2, RED);
+
+        for name in ["saved.png", "saved.bmp"] {
+            let path = temp_path(name);
+            save_image(&image, &path, 90).unwrap();
+            let (opened, _) = open_image(&path).unwrap();
+            assert_eq!(opened, image, "{}", name);
         }
-        Tool::Eraser => {
-            let (r, g, b, a) = state.background_color.as_rgba8();
-            Rgba([r, g, b, a])
+
+        let path = temp_path("saved.jpg");
+        save_image(&image, &path, 90).unwrap();
+        let (opened, _) = open_image(&path).unwrap();
+        assert_eq!(opened.dimensions(), (6, 4));
+        assert_eq!(opened.get_pixel(0, 0)[3], 255);
+    }
+
+    #[test]
+    fn square_dab_blends_half_alpha_over_opaque_pixel() {
+        let mut state = test_state(8, 8);
+        state.brush_shape = BrushShape::Square;
+        state.brush_size = 1;
+        let mut image = RgbaImage::from_pixel(8, 8, WHITE);
+
+        stamp_brush(&mut image, &state, StampMode::Deposit, 4, 4, Rgba([255, 0, 0, 128]), None);
+
+        let blended = image.get_pixel(4, 4);
+        assert_eq!(blended[0], 255);
+        assert!(blended[1].abs_diff(128) <= 1 && blended[2].abs_diff(128) <= 1, "{:?}", blended);
+        assert_eq!(blended[3], 255);
+        assert_eq!(*image.get_pixel(3, 3), *blended);
+        assert_eq!(*image.get_pixel(2, 4), WHITE);
+        assert_eq!(*image.get_pixel(6, 4), WHITE);
+    }
+
+    #[test]
+    fn opaque_square_dab_matches_a_replaced_block() {
+        let mut state = test_state(16, 16);
+        state.brush_shape = BrushShape::Square;
+        state.brush_size = 3;
+        let mut stamped = RgbaImage::from_pixel(16, 16, WHITE);
+        let mut replaced = stamped.clone();
+
+        for (x, y) in [(5, 5), (0, 0), (15, 9)] {
+            stamp_brush(&mut stamped, &state, StampMode::Deposit, x, y, RED, None);
+            let block = RgbaImage::from_pixel(7, 7, RED);
+            image::imageops::replace(&mut replaced, &block, x as i64 - 3, y as i64 - 3);
         }
-    };
 
-    match state.brush_shape {
-        BrushShape::Square => {
-            let x_min = (x_center - radius).max(0) as u32;
-            let x_max = (x_center + radius + 1).min(image.width() as i32) as u32;
-            let y_min = (y_center - radius).max(0) as u32;
-            let y_max = (y_center + radius + 1).min(image.height() as i32) as u32;
-
-            let brush = RgbaImage::from_pixel(
-                (x_max - x_min) as u32,
-                (y_max - y_min) as u32,
-                color,
+        assert_eq!(stamped, replaced);
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture` to compare writing square
+    // dabs in place against the old per-stamp `from_pixel` + `replace` approach.
+    #[test]
+    #[ignore]
+    fn time_square_dabs_against_per_stamp_images() {
+        let mut state = test_state(1024, 1024);
+        state.brush_shape = BrushShape::Square;
+        state.brush_size = 8;
+        let mut image = RgbaImage::from_pixel(1024, 1024, WHITE);
+        let stamps: i32 = 100_000;
+
+        let started = Instant::now();
+        for index in 0..stamps {
+            let offset = index % 1000;
+            stamp_brush(&mut image, &state, StampMode::Deposit, offset, offset, RED, None);
+        }
+        let in_place = started.elapsed();
+        let started = Instant::now();
+        for index in 0..stamps {
+            let offset = (index % 1000) as i64;
+            let block = RgbaImage::from_pixel(17, 17, RED);
+            image::imageops::replace(&mut image, &block, offset - 8, offset - 8);
+        }
+        let per_stamp = started.elapsed();
+        println!("{} square dabs: in place {:?}, per-stamp image {:?}", stamps, in_place, per_stamp);
+    }
+
+    #[test]
+    fn overlapping_dabs_do_not_darken_past_stroke_opacity() {
+        let mut state = test_state(8, 8);
+        state.brush_shape = BrushShape::Square;
+        state.brush_size = 2;
+        state.brush_opacity = 0.5;
+        state.brush_flow = 0.6;
+        let mut stroke = StrokeBuffer::new(8, 8);
+
+        let StrokeBuffer { layer, coverage } = &mut stroke;
+        for x_center in [3, 4, 4, 5] {
+            stamp_brush(layer, &state, StampMode::Deposit, x_center, 4, RED, Some(coverage));
+        }
+        let mut image = RgbaImage::from_pixel(8, 8, WHITE);
+        stroke.composite(&mut image);
+
+        let overlapped = image.get_pixel(4, 4);
+        assert_eq!(overlapped[0], 255);
+        assert!(overlapped[1].abs_diff(128) <= 1, "{:?}", overlapped);
+        assert!(stroke.coverage.iter().all(|&coverage| coverage <= 0.5));
+    }
+
+    #[test]
+    fn rapid_stroke_batches_stay_bounded_at_the_canvas_edges() {
+        let mut state = test_state(64, 48);
+        state.brush_opacity = 0.6;
+        state.symmetry = Symmetry::Both;
+        let positions: Vec<Point> = (0..2000)
+            .map(|index| {
+                let t = index as f64 / 1999.0;
+                image_to_screen(&state, Point::new(-40.0 + 144.0 * t, 90.0 - 140.0 * t))
+            })
+            .collect();
+
+        // The mirrored path spans x -41..=104 and y -50..=97; the brush adds its radius of 5,
+        // plus one pixel of antialiasing when that is on.
+        state.brush_shape = BrushShape::Circle;
+        for (antialiasing, reach) in [(false, 5.0), (true, 6.0)] {
+            state.brush_antialiasing = antialiasing;
+            state.stroke_dirty = None;
+            for tool in [Tool::Brush, Tool::Eraser, Tool::Smudge] {
+                state.current_tool = tool;
+                begin_stroke(&mut state);
+                for batch in positions.chunks(7) {
+                    stamp_positions(&mut state, batch);
+                }
+                if let Some(stroke) = &state.stroke {
+                    assert!(stroke.lock().unwrap().coverage.iter().all(|&coverage| coverage <= 0.6));
+                }
+                if let Some(erase) = &state.erase_stroke {
+                    assert!(erase.lock().unwrap().coverage.iter().all(|&coverage| coverage <= 0.6));
+                }
+                commit_stroke(&mut state);
+            }
+            assert_eq!(
+                state.stroke_dirty,
+                Some(Rect::new(-41.0 - reach, -50.0 - reach, 105.0 + reach, 98.0 + reach))
             );
-            replace(&mut *image, &brush, x_min as i64, y_min as i64);
         }
-        BrushShape::Circle => {
-            for x in (x_center - radius).max(0)..=(x_center + radius).min(image.width() as i32 - 1) {
-                for y in (y_center - radius).max(0)..=(y_center + radius).min(image.height() as i32 - 1) {
-                    let dx = x - x_center;
-                    let dy = y - y_center;
-                    if dx * dx + dy * dy <= radius * radius {
-                        image.put_pixel(x as u32, y as u32, color);
-                    }
+        assert_eq!(state.image.read().unwrap().dimensions(), (64, 48));
+    }
+
+    #[test]
+    fn stamping_batches_while_another_thread_reads_does_not_deadlock() {
+        let mut state = test_state(128, 128);
+        let image = state.image.clone();
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let reader = {
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut reads = 0;
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    assert_eq!(image.read().unwrap().dimensions(), (128, 128));
+                    reads += 1;
+                }
+                reads
+            })
+        };
+
+        for index in 0..500 {
+            let positions: Vec<Point> = (0..16)
+                .map(|step| Point::new(((index * 7 + step) % 160) as f64 - 16.0, (index % 128) as f64))
+                .collect();
+            assert!(stamp_positions(&mut state, &positions).is_some());
+        }
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+
+        assert!(reader.join().unwrap() > 0);
+    }
+
+    #[test]
+    fn hardness_controls_circle_falloff() {
+        let center_row = |mask: &[f32]| -> Vec<f32> { mask[4 * 9..5 * 9].to_vec() };
+
+        let hard = center_row(&circle_mask(4, false, 1.0));
+        assert_eq!(hard, vec![1.0; 9]);
+
+        let soft = center_row(&circle_mask(4, false, 0.0));
+        assert_eq!(soft[4], 1.0);
+        assert!((soft[6] - 0.5).abs() < 1e-6);
+        assert_eq!(soft[8], 0.0);
+        assert!(soft[4..].windows(2).all(|pair| pair[0] > pair[1]));
+
+        let half = center_row(&circle_mask(4, false, 0.5));
+        assert_eq!(half[6], 1.0);
+        assert!((half[7] - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn zoom_steps_clamp_and_keep_the_anchor_fixed() {
+        let mut state = test_state(100, 100);
+        let anchor = Point::new(120.0, 80.0);
+        let anchored = screen_to_image(&state, anchor);
+
+        for _ in 0..40 {
+            let zoom = state.zoom * ZOOM_STEP;
+            zoom_around(&mut state, zoom, anchor);
+        }
+        assert_eq!(state.zoom, MAX_ZOOM);
+        assert_eq!(state.zoom_input, "1600");
+        let after = screen_to_image(&state, anchor);
+        assert!((after.x - anchored.x).abs() < 1e-9 && (after.y - anchored.y).abs() < 1e-9);
+
+        for _ in 0..40 {
+            let zoom = state.zoom / ZOOM_STEP;
+            zoom_around(&mut state, zoom, anchor);
+        }
+        assert_eq!(state.zoom, MIN_ZOOM);
+    }
+
+    #[test]
+    fn pan_offset_shifts_coordinates_and_keeps_a_margin_visible() {
+        let mut state = test_state(200, 100);
+        state.zoom = 2.0;
+        state.pan_offset = Point::new(50.0, -20.0);
+        assert_eq!(screen_to_image(&state, Point::new(150.0, 80.0)), Point::new(50.0, 50.0));
+        assert_eq!(image_to_screen(&state, Point::new(50.0, 50.0)), Point::new(150.0, 80.0));
+
+        state.pan_offset = Point::new(10_000.0, 10_000.0);
+        clamp_pan(&mut state);
+        assert_eq!(state.pan_offset, Point::new(CANVAS_WIDTH - PAN_MARGIN, CANVAS_HEIGHT - PAN_MARGIN));
+
+        state.pan_offset = Point::new(-10_000.0, -10_000.0);
+        clamp_pan(&mut state);
+        assert_eq!(state.pan_offset, Point::new(PAN_MARGIN - 400.0, PAN_MARGIN - 200.0));
+    }
+
+    #[test]
+    fn viewport_mapping_on_a_large_image() {
+        let mut state = test_state(1600, 1200);
+        let image = state.image.read().unwrap().clone();
+        let cases = [
+            (0.5, Point::ZERO, Rect::new(0.0, 0.0, 180.0, 135.0)),
+            (1.0, Point::ZERO, Rect::new(0.0, 0.0, 90.0, 67.5)),
+            (1.0, Point::new(-800.0, -600.0), Rect::new(90.0, 67.5, 180.0, 135.0)),
+            (2.0, Point::new(-2400.0, -1800.0), Rect::new(135.0, 101.25, 180.0, 135.0)),
+        ];
+        for (zoom, pan, expected) in cases {
+            state.zoom = zoom;
+            state.pan_offset = pan;
+            assert_eq!(minimap_viewport(&state, &image), expected, "zoom {} pan {:?}", zoom, pan);
+            let top_left = screen_to_image(&state, Point::ZERO);
+            assert_eq!(top_left, Point::new(-pan.x / zoom, -pan.y / zoom));
+        }
+
+        assert_eq!(screen_to_image(&state, Point::new(CANVAS_WIDTH, CANVAS_HEIGHT)), Point::new(1600.0, 1200.0));
+        assert_eq!(screen_to_image(&state, Point::new(0.0, 0.0)), Point::new(1200.0, 900.0));
+    }
+
+    #[test]
+    fn layers_add_delete_and_reorder() {
+        let mut state = test_state(4, 4);
+        add_layer(&mut state);
+        add_layer(&mut state);
+        let names = |state: &AppState| state.layers.iter().map(|layer| layer.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&state), ["Background", "Layer 2", "Layer 3"]);
+        assert_eq!(state.active_layer, 2);
+        assert!(Arc::ptr_eq(&state.image, &state.layers[2].image));
+
+        move_layer(&mut state, true);
+        assert_eq!(state.active_layer, 2);
+        move_layer(&mut state, false);
+        assert_eq!(names(&state), ["Background", "Layer 3", "Layer 2"]);
+        assert_eq!(state.active_layer, 1);
+
+        delete_layer(&mut state);
+        assert_eq!(names(&state), ["Background", "Layer 2"]);
+        assert_eq!(state.active_layer, 0);
+        delete_layer(&mut state);
+        delete_layer(&mut state);
+        assert_eq!(names(&state), ["Layer 2"]);
+        assert!(Arc::ptr_eq(&state.image, &state.layers[0].image));
+    }
+
+    #[test]
+    fn stroke_preview_composites_at_the_active_layer() {
+        let mut state = test_state(2, 1);
+        add_layer(&mut state);
+        state.layers[1].image.write().unwrap().put_pixel(0, 0, BLACK);
+        let stroke = RgbaImage::from_pixel(2, 1, RED);
+
+        let below = composite_region(&state.layers, (0, 0, 2, 1), Some((0, &stroke)));
+        assert_eq!(*below.get_pixel(0, 0), BLACK);
+        assert_eq!(*below.get_pixel(1, 0), RED);
+
+        let above = composite_region(&state.layers, (0, 0, 2, 1), Some((1, &stroke)));
+        assert_eq!(*above.get_pixel(0, 0), RED);
+
+        let patch = composite_region(&state.layers, (1, 0, 1, 1), Some((0, &stroke)));
+        assert_eq!(patch.dimensions(), (1, 1));
+        assert_eq!(*patch.get_pixel(0, 0), RED);
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture` to compare a full
+    // recomposite against the dirty-rect patch uploaded while a stroke is in progress.
+    #[test]
+    #[ignore]
+    fn time_stroke_preview_against_full_composite() {
+        let mut state = test_state(2000, 2000);
+        add_layer(&mut state);
+        add_layer(&mut state);
+        let stroke = RgbaImage::from_pixel(2000, 2000, RED);
+
+        let started = Instant::now();
+        for _ in 0..10 {
+            composite_region(&state.layers, (0, 0, 2000, 2000), Some((1, &stroke)));
+        }
+        let full = started.elapsed() / 10;
+        let started = Instant::now();
+        for _ in 0..10 {
+            composite_region(&state.layers, (900, 900, 64, 64), Some((1, &stroke)));
+        }
+        let patch = started.elapsed() / 10;
+        println!("full composite: {:?}, 64x64 dirty patch: {:?}", full, patch);
+        assert!(patch < full);
+    }
+
+    #[test]
+    fn dirty_flag_follows_edits_saves_and_undo() {
+        let mut state = test_state(6, 6);
+        assert!(!state.dirty);
+
+        fill_at(&mut state, Point::new(-5.0, -5.0));
+        add_polygon_point(&mut state, Point::new(1.0, 1.0), 1);
+        assert!(!state.dirty, "clicks that change no pixels leave the document clean");
+
+        fill_at(&mut state, Point::new(2.0, 2.0));
+        assert!(state.dirty);
+
+        save_document(&mut state, &temp_path("dirty.png")).unwrap();
+        assert!(!state.dirty);
+
+        undo(&mut state);
+        assert!(state.dirty, "undoing past the save point makes the document dirty again");
+    }
+
+    #[test]
+    fn filter_preview_leaves_the_layer_until_applied() {
+        let mut state = test_state(3, 2);
+        state.image.write().unwrap().put_pixel(1, 1, RED);
+        let before = state.image.read().unwrap().clone();
+        let params = filter_params(&state);
+        let index = filters::registry(&params)
+            .iter()
+            .position(|filter| filter.name() == "filter-invert")
+            .unwrap();
+
+        let preview = composite_filter_preview(&state, &before, index, &params);
+        assert_eq!(*preview.get_pixel(1, 1), Rgba([0, 255, 255, 255]));
+        assert_eq!(*state.image.read().unwrap(), before);
+
+        let filter = filters::registry(&params).swap_remove(index);
+        apply_filter(&mut state, filter);
+        assert_eq!(*state.image.read().unwrap(), preview);
+    }
+
+    #[test]
+    fn clear_canvas_fills_with_the_background_and_confirms_only_when_dirty() {
+        let mut state = test_state(4, 3);
+        state.background_color = Color::rgba8(10, 20, 30, 128);
+
+        request_clear_canvas(&mut state);
+        assert!(!state.clear_pending, "a clean document is cleared without asking");
+        assert!(state.image.read().unwrap().pixels().all(|&pixel| pixel == Rgba([10, 20, 30, 128])));
+        assert!(state.dirty);
+
+        state.image.write().unwrap().put_pixel(1, 1, RED);
+        request_clear_canvas(&mut state);
+        assert!(state.clear_pending, "unsaved work asks for confirmation");
+        assert_eq!(*state.image.read().unwrap().get_pixel(1, 1), RED);
+
+        clear_canvas(&mut state);
+        assert_eq!(*state.image.read().unwrap().get_pixel(1, 1), Rgba([10, 20, 30, 128]));
+        undo(&mut state);
+        assert_eq!(*state.image.read().unwrap().get_pixel(1, 1), RED);
+    }
+
+    #[test]
+    fn extract_channel_writes_an_opaque_grayscale_of_one_channel() {
+        let image = RgbaImage::from_pixel(2, 2, Rgba([10, 20, 30, 40]));
+        for (channel, value) in [(Channel::Red, 10), (Channel::Green, 20), (Channel::Blue, 30), (Channel::Alpha, 40)] {
+            let extracted = extract_channel(&image, channel);
+            assert_eq!(extracted.dimensions(), (2, 2));
+            assert!(extracted.pixels().all(|&pixel| pixel == Rgba([value, value, value, 255])));
+        }
+    }
+
+    #[test]
+    fn stroke_positions_space_dabs_by_half_the_brush() {
+        let mut state = test_state(8, 8);
+        state.brush_size = 4;
+        let positions = stroke_positions(&state, Point::new(0.0, 0.0), Point::new(10.0, 0.0));
+        assert_eq!(positions, [2.0, 4.0, 6.0, 8.0, 10.0].map(|x| Point::new(x, 0.0)));
+
+        let positions = stroke_positions(&state, Point::new(0.0, 0.0), Point::new(3.0, 4.0));
+        assert_eq!(positions.len(), 3);
+        assert!(positions.windows(2).all(|pair| pair[0].distance(pair[1]) <= 2.0));
+        assert_eq!(positions.last(), Some(&Point::new(3.0, 4.0)));
+
+        let positions = stroke_positions(&state, Point::new(5.0, 5.0), Point::new(5.0, 5.0));
+        assert_eq!(positions, [Point::new(5.0, 5.0)]);
+
+        state.zoom = 2.0;
+        assert_eq!(stroke_positions(&state, Point::new(0.0, 0.0), Point::new(16.0, 0.0)).len(), 4);
+
+        state.current_tool = Tool::Pencil;
+        assert_eq!(stroke_positions(&state, Point::new(0.0, 0.0), Point::new(16.0, 0.0)).len(), 8);
+    }
+
+    #[test]
+    fn blend_modes_follow_their_formulas() {
+        let cases = [
+            (BlendMode::Normal, 0.25, 0.5, 0.5),
+            (BlendMode::Multiply, 0.5, 0.5, 0.25),
+            (BlendMode::Screen, 0.5, 0.5, 0.75),
+            (BlendMode::Overlay, 0.25, 0.5, 0.25),
+            (BlendMode::Overlay, 0.75, 0.5, 0.75),
+            (BlendMode::Add, 0.75, 0.5, 1.0),
+        ];
+        for (mode, backdrop, source, expected) in cases {
+            assert!((blend_channel(mode, backdrop, source) - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn multiply_layer_darkens_the_composite() {
+        let mut state = test_state(2, 2);
+        *state.image.write().unwrap() = RgbaImage::from_pixel(2, 2, Rgba([128, 255, 255, 255]));
+        add_layer(&mut state);
+        *state.image.write().unwrap() = RgbaImage::from_pixel(2, 2, Rgba([128, 128, 0, 255]));
+        Arc::make_mut(&mut state.layers)[1].blend_mode = BlendMode::Multiply;
+
+        assert_eq!(*composite_layers(&state.layers).get_pixel(1, 1), Rgba([64, 128, 0, 255]));
+
+        Arc::make_mut(&mut state.layers)[1].opacity = 0.0;
+        assert_eq!(*composite_layers(&state.layers).get_pixel(1, 1), Rgba([128, 255, 255, 255]));
+    }
+
+    #[test]
+    fn resize_keeps_the_aspect_ratio_or_uses_both_inputs() {
+        let mut state = test_state(40, 30);
+        add_layer(&mut state);
+        state.resize_lock_aspect = true;
+        state.resize_width_input = "20".to_string();
+        resize_document(&mut state);
+        assert_eq!(state.resize_height_input, "15");
+        assert!(state.layers.iter().all(|layer| layer.image.read().unwrap().dimensions() == (20, 15)));
+
+        state.resize_lock_aspect = false;
+        state.resize_width_input = " 7 ".to_string();
+        state.resize_height_input = "100".to_string();
+        resize_document(&mut state);
+        assert_eq!(state.image.read().unwrap().dimensions(), (7, 100));
+
+        state.resize_lock_aspect = true;
+        state.resize_width_input = "1".to_string();
+        resize_document(&mut state);
+        assert_eq!(state.image.read().unwrap().dimensions(), (1, 14));
+
+        for (width, height) in [("0", "5"), ("abc", "5"), ("5", "0"), ("5", "9000")] {
+            state.resize_lock_aspect = false;
+            state.resize_width_input = width.to_string();
+            state.resize_height_input = height.to_string();
+            resize_document(&mut state);
+            assert_eq!(state.image.read().unwrap().dimensions(), (1, 14), "{}x{}", width, height);
+        }
+        assert_eq!(state.status_message, StatusMessage::new("status-resize-height-invalid").with_arg("max", 8192));
+    }
+
+    #[test]
+    fn crop_clamps_the_region_to_the_canvas() {
+        let mut state = test_state(10, 8);
+        state.image.write().unwrap().put_pixel(6, 5, RED);
+
+        crop_document(&mut state, Rect::new(4.2, 3.0, 25.0, 30.0));
+        assert_eq!(state.image.read().unwrap().dimensions(), (6, 5));
+        assert_eq!(*state.image.read().unwrap().get_pixel(2, 2), RED);
+        let cropped = StatusMessage::new("status-cropped").with_arg("width", 6).with_arg("height", 5);
+        assert_eq!(state.status_message, cropped);
+
+        crop_document(&mut state, Rect::new(-3.0, -3.0, 2.0, 1.0));
+        assert_eq!(state.image.read().unwrap().dimensions(), (2, 1));
+
+        for empty in [Rect::new(5.0, 0.0, 9.0, 1.0), Rect::new(1.0, 0.0, 1.2, 1.0)] {
+            crop_document(&mut state, empty);
+            assert_eq!(state.image.read().unwrap().dimensions(), (2, 1));
+            assert_eq!(state.status_message, StatusMessage::new("status-crop-empty"));
+        }
+    }
+
+    #[test]
+    fn flips_mirror_every_layer_and_cancel_out_when_repeated() {
+        let mut state = test_state(5, 3);
+        state.image.write().unwrap().put_pixel(0, 0, RED);
+        add_layer(&mut state);
+        state.image.write().unwrap().put_pixel(1, 2, BLACK);
+        let original = composite_layers(&state.layers);
+
+        map_layers(&mut state, |_, image| flip_horizontal(image));
+        assert_eq!(*state.layers[0].image.read().unwrap().get_pixel(4, 0), RED);
+        assert_eq!(*state.layers[1].image.read().unwrap().get_pixel(3, 2), BLACK);
+
+        map_layers(&mut state, |_, image| flip_vertical(image));
+        assert_eq!(*state.layers[0].image.read().unwrap().get_pixel(4, 2), RED);
+        assert_eq!(*state.layers[1].image.read().unwrap().get_pixel(3, 0), BLACK);
+
+        map_layers(&mut state, |_, image| flip_vertical(image));
+        map_layers(&mut state, |_, image| flip_horizontal(image));
+        assert_eq!(composite_layers(&state.layers), original);
+    }
+
+    #[test]
+    fn quarter_turns_move_pixels_and_round_trip() {
+        let mut state = test_state(4, 2);
+        state.image.write().unwrap().put_pixel(3, 0, RED);
+        let original = state.image.read().unwrap().clone();
+
+        map_layers(&mut state, |_, image| rotate90(image));
+        assert_eq!(state.image.read().unwrap().dimensions(), (2, 4));
+        assert_eq!(*state.image.read().unwrap().get_pixel(1, 3), RED);
+
+        map_layers(&mut state, |_, image| rotate270(image));
+        assert_eq!(*state.image.read().unwrap(), original);
+
+        map_layers(&mut state, |_, image| rotate180(image));
+        assert_eq!(*state.image.read().unwrap().get_pixel(0, 1), RED);
+        map_layers(&mut state, |_, image| rotate180(image));
+        assert_eq!(*state.image.read().unwrap(), original);
+
+        for _ in 0..4 {
+            map_layers(&mut state, |_, image| rotate90(image));
+        }
+        assert_eq!(*state.image.read().unwrap(), original);
+    }
+
+    #[test]
+    fn arbitrary_rotation_grows_the_canvas_and_fills_the_corners() {
+        let image = RgbaImage::from_fn(40, 30, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        assert_eq!(rotate_arbitrary(&image, 0.0, RED), image);
+        assert_eq!(rotate_arbitrary(&image, 90.0, RED).dimensions(), (30, 40));
+        assert_eq!(rotate_arbitrary(&image, 180.0, RED).dimensions(), (40, 30));
+
+        let rotated = rotate_arbitrary(&image, 45.0, RED);
+        assert_eq!(rotated.dimensions(), (49, 49));
+        assert_eq!(*rotated.get_pixel(0, 0), RED);
+        assert_eq!(*rotated.get_pixel(48, 48), RED);
+        assert_eq!(rotated.get_pixel(24, 24)[3], 255);
+
+        let mut state = test_state(40, 30);
+        add_layer(&mut state);
+        state.background_color = Color::rgb8(0, 0, 255);
+        state.rotate_degrees_input = "30".to_string();
+        rotate_document(&mut state);
+        assert_eq!(state.layers[0].image.read().unwrap().dimensions(), (50, 46));
+        assert_eq!(*state.layers[0].image.read().unwrap().get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+        assert_eq!(state.layers[1].image.read().unwrap().get_pixel(0, 0)[3], 0);
+
+        state.rotate_degrees_input = "a lot".to_string();
+        rotate_document(&mut state);
+        assert_eq!(state.image.read().unwrap().dimensions(), (50, 46));
+        assert_eq!(state.status_message, StatusMessage::new("status-rotate-invalid"));
+    }
+
+    #[test]
+    fn spray_stays_within_its_radius_and_repeats_for_a_seed() {
+        let spray = |seed: u64| {
+            let mut state = test_state(64, 64);
+            state.brush_size = 6;
+            state.spray_density = 400.0;
+            state.brush_color = Color::BLACK;
+            let mut rng = StdRng::seed_from_u64(seed);
+            for center in [Point::new(32.0, 32.0), Point::new(0.0, 63.0)] {
+                let center = image_to_screen(&state, center);
+                for _ in 0..10 {
+                    spray_at(&mut state, center, &mut rng);
                 }
             }
+            state.image.read().unwrap().clone()
+        };
+
+        let image = spray(7);
+        let painted: Vec<(u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel != WHITE)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(painted.len() > 50);
+        for (x, y) in painted {
+            let pixel = Point::new(x as f64 + 0.5, y as f64 + 0.5);
+            let nearest = pixel.distance(Point::new(32.0, 32.0)).min(pixel.distance(Point::new(0.0, 63.0)));
+            assert!(nearest <= 6.0 + std::f64::consts::SQRT_2, "({}, {}) is {} away", x, y, nearest);
         }
+        assert_eq!(spray(7), image);
     }
 
-    let dirty_rect = Rect::from_origin_size(
-        Point::new(
-            (x_center - radius) as f64 * 800.0 / image.width() as f64,
-            (y_center - radius) as f64 * 600.0 / image.height() as f64,
-        ),
-        (
-            (radius * 2) as f64 * 800.0 / image.width() as f64,
-            (radius * 2) as f64 * 600.0 / image.height() as f64,
-        ),
-    );
-    ctx.request_paint_rect(dirty_rect);
+    #[test]
+    fn recent_colors_move_repeats_to_the_front_and_cap_the_list() {
+        let mut state = test_state(2, 2);
+        let gray = |value: u8| Color::rgb8(value, value, value);
+        let recent = |state: &AppState| state.recent_colors.iter().map(|color| color.as_rgba8().0).collect::<Vec<_>>();
+
+        for value in 1..=3 {
+            remember_color(&mut state, gray(value));
+        }
+        remember_color(&mut state, gray(1));
+        assert_eq!(recent(&state), [1, 3, 2]);
+
+        for value in 10..20 {
+            remember_color(&mut state, gray(value));
+        }
+        assert_eq!(state.recent_colors.len(), MAX_RECENT_COLORS);
+        assert_eq!(recent(&state), [19, 18, 17, 16, 15, 14, 13, 12]);
+
+        remember_color(&mut state, Color::rgba8(12, 12, 12, 100));
+        assert_eq!(recent(&state)[..2], [12, 19], "a different alpha counts as a new color");
+        assert_eq!(state.recent_colors.len(), MAX_RECENT_COLORS);
+    }
+
+    #[test]
+    fn hex_colors_parse_and_format() {
+        let rgba = |color: Option<Color>| color.map(|color| color.as_rgba8());
+        assert_eq!(rgba(parse_hex_color("#FF8000")), Some((255, 128, 0, 255)));
+        assert_eq!(rgba(parse_hex_color("  #ff800080 ")), Some((255, 128, 0, 128)));
+        for bad in ["", "#", "FF8000", "#FF800", "#FF80000", "#GG8000", "#+F8000", "#FF80€0", "#FF8000FF00"] {
+            assert!(parse_hex_color(bad).is_none(), "{:?}", bad);
+        }
+
+        assert_eq!(format_hex_color(Color::rgb8(1, 171, 255)), "#01ABFF");
+        assert_eq!(format_hex_color(Color::rgba8(1, 171, 255, 16)), "#01ABFF10");
+        for hex in ["#000000", "#12345678"] {
+            assert_eq!(format_hex_color(parse_hex_color(hex).unwrap()), hex);
+        }
+    }
+
+    #[test]
+    fn brush_alpha_is_clamped_and_reaches_the_composite() {
+        let mut state = test_state(9, 9);
+        state.color_r_input = "0".to_string();
+        state.color_g_input = "0".to_string();
+        state.color_b_input = "0".to_string();
+        for (input, alpha) in [("300", 255), ("-20", 0), ("", 255), ("x", 255), (" 128 ", 128)] {
+            state.color_a_input = input.to_string();
+            update_brush_color(&mut state);
+            assert_eq!(state.brush_color.as_rgba8().3, alpha, "{:?}", input);
+        }
+
+        state.brush_shape = BrushShape::Square;
+        state.brush_size = 1;
+        let center = image_to_screen(&state, Point::new(4.0, 4.0));
+        stamp_positions(&mut state, &[center]);
+
+        let composite = composite_layers(&state.layers);
+        assert_eq!(*composite.get_pixel(4, 4), Rgba([127, 127, 127, 255]));
+        assert_eq!(*composite.get_pixel(1, 1), WHITE);
+    }
+
+    #[test]
+    fn tool_shortcuts_map_to_distinct_tools() {
+        let bindings = [
+            (Code::KeyB, Tool::Brush),
+            (Code::KeyI, Tool::Picker),
+            (Code::KeyG, Tool::Fill),
+            (Code::KeyP, Tool::Polygon),
+            (Code::KeyM, Tool::Select),
+            (Code::KeyU, Tool::Line),
+            (Code::KeyR, Tool::Rectangle),
+            (Code::KeyO, Tool::Ellipse),
+            (Code::KeyS, Tool::Smudge),
+            (Code::KeyC, Tool::Crop),
+            (Code::KeyA, Tool::Spray),
+            (Code::KeyN, Tool::Pencil),
+        ];
+        for (code, tool) in bindings.clone() {
+            assert!(tool_for_key(code) == Some(tool), "{:?}", code);
+        }
+        let tools: std::collections::HashSet<Tool> = bindings.into_iter().map(|(_, tool)| tool).collect();
+        assert_eq!(tools.len(), 12);
+        for code in [Code::KeyZ, Code::KeyE, Code::Digit1, Code::Space, Code::Escape] {
+            assert!(tool_for_key(code).is_none(), "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn pixel_under_cursor_reads_inside_the_image_only() {
+        let mut state = test_state(6, 4);
+        state.image.write().unwrap().put_pixel(5, 3, RED);
+        assert_eq!(pixel_under_cursor(&state), None);
+
+        state.cursor_pos = Some(Point::new(5.9, 3.2));
+        assert_eq!(pixel_under_cursor(&state), Some((5, 3, RED)));
+        state.cursor_pos = Some(Point::new(0.0, 0.0));
+        assert_eq!(pixel_under_cursor(&state), Some((0, 0, WHITE)));
+
+        for outside in [Point::new(-0.1, 1.0), Point::new(1.0, -0.5), Point::new(6.0, 1.0), Point::new(1.0, 4.0)] {
+            state.cursor_pos = Some(outside);
+            assert_eq!(pixel_under_cursor(&state), None, "{:?}", outside);
+        }
+
+        // With a transparent layer active, the pixel still comes from the layers below it.
+        add_layer(&mut state);
+        state.image.write().unwrap().put_pixel(1, 1, BLACK);
+        state.cursor_pos = Some(Point::new(5.0, 3.0));
+        assert_eq!(pixel_under_cursor(&state), Some((5, 3, RED)));
+        state.cursor_pos = Some(Point::new(1.0, 1.0));
+        assert_eq!(pixel_under_cursor(&state), Some((1, 1, BLACK)));
+        Arc::make_mut(&mut state.layers)[0].visible = false;
+        state.cursor_pos = Some(Point::new(5.0, 3.0));
+        assert_eq!(pixel_under_cursor(&state).unwrap().2[3], 0);
+    }
+
+    #[test]
+    fn selection_region_covers_the_selected_bounding_box() {
+        let mut state = test_state(8, 6);
+        assert!(selection_region(&state).is_none());
+
+        state.image.write().unwrap().put_pixel(2, 1, RED);
+        add_layer(&mut state);
+        state.image.write().unwrap().put_pixel(4, 3, BLACK);
+        let mut mask = vec![0.0; 8 * 6];
+        for (x, y) in [(2, 1), (4, 3), (3, 2)] {
+            mask[y * 8 + x] = 1.0;
+        }
+        mask[5 * 8 + 7] = 0.4;
+        state.selection = Some(Arc::new(Selection::from_mask(mask, 8)));
+
+        let region = selection_region(&state).unwrap();
+        assert_eq!(region.dimensions(), (3, 3));
+        assert_eq!(*region.get_pixel(0, 0), RED);
+        assert_eq!(*region.get_pixel(2, 2), BLACK);
+        assert_eq!(*region.get_pixel(2, 0), WHITE);
+
+        state.selection = Some(Arc::new(Selection::from_mask(vec![0.4; 8 * 6], 8)));
+        assert!(selection_region(&state).is_none());
+    }
+
+    #[test]
+    fn constrain_square_keeps_the_shorter_side_in_every_quadrant() {
+        let start = Point::new(10.0, 10.0);
+        assert_eq!(constrain_square(start, Point::new(30.0, 15.0)), Point::new(15.0, 15.0));
+        assert_eq!(constrain_square(start, Point::new(4.0, 30.0)), Point::new(4.0, 16.0));
+        assert_eq!(constrain_square(start, Point::new(-20.0, -2.0)), Point::new(-2.0, -2.0));
+        assert_eq!(constrain_square(start, Point::new(13.0, -40.0)), Point::new(13.0, 7.0));
+        assert_eq!(constrain_square(start, Point::new(10.0, 50.0)), start);
+    }
+
+    #[test]
+    fn dynamic_brush_size_shrinks_with_speed_within_range() {
+        assert_eq!(dynamic_brush_size(20, 50.0, Duration::ZERO), 20);
+        assert_eq!(dynamic_brush_size(20, 0.0, Duration::from_millis(16)), 20);
+        assert_eq!(dynamic_brush_size(0, 0.0, Duration::from_millis(16)), 1);
+
+        let sizes: Vec<u32> = [1.0, 10.0, 100.0, 1000.0, 1e6]
+            .iter()
+            .map(|&distance| dynamic_brush_size(20, distance, Duration::from_millis(16)))
+            .collect();
+        assert!(sizes.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", sizes);
+        assert!(sizes.iter().all(|&size| (1..=20).contains(&size)));
+        assert!(sizes[0] >= 18, "slow strokes stay near the full size");
+        assert_eq!(sizes.last(), Some(&1));
+    }
+
+    #[test]
+    fn mirror_centers_reflect_across_the_canvas_midlines() {
+        let centers = vec![(1, 2), (9, 0)];
+        assert_eq!(mirror_centers(centers.clone(), Symmetry::None, 10, 6), centers);
+        assert_eq!(mirror_centers(centers.clone(), Symmetry::Vertical, 10, 6), [(1, 2), (8, 2), (9, 0), (0, 0)]);
+        assert_eq!(mirror_centers(centers.clone(), Symmetry::Horizontal, 10, 6), [(1, 2), (1, 3), (9, 0), (9, 5)]);
+        assert_eq!(
+            mirror_centers(vec![(1, 2)], Symmetry::Both, 10, 6),
+            [(1, 2), (8, 2), (1, 3), (8, 3)]
+        );
+        assert_eq!(mirror_centers(vec![(-4, 8)], Symmetry::Vertical, 10, 6), [(-4, 8), (13, 8)]);
+    }
+
+    #[test]
+    fn gradients_hit_their_endpoint_colors_and_clamp_beyond() {
+        let mut image = RgbaImage::new(15, 1);
+        fill_linear_gradient(&mut image, Point::new(2.5, 0.5), Point::new(12.5, 0.5), BLACK, WHITE, None);
+        let values: Vec<u8> = image.pixels().map(|pixel| pixel[0]).collect();
+        assert_eq!(values[..3], [0, 0, 0]);
+        assert_eq!(values[7], 128);
+        assert_eq!(values[12..], [255, 255, 255]);
+        assert!(values.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(image.pixels().all(|pixel| pixel[3] == 255));
+
+        let mut image = RgbaImage::new(11, 11);
+        fill_radial_gradient(&mut image, Point::new(5.5, 5.5), Point::new(5.5, 0.5), RED, BLACK, None);
+        assert_eq!(*image.get_pixel(5, 5), RED);
+        assert_eq!(*image.get_pixel(5, 0), BLACK);
+        assert_eq!(*image.get_pixel(0, 0), BLACK);
+        assert_eq!(image.get_pixel(3, 5), image.get_pixel(5, 7));
+
+        let mut image = RgbaImage::from_pixel(2, 1, WHITE);
+        fill_linear_gradient(&mut image, Point::ZERO, Point::new(2.0, 0.0), BLACK, BLACK, Some(&[0.0, 1.0]));
+        assert_eq!(*image.get_pixel(0, 0), WHITE);
+        assert_eq!(*image.get_pixel(1, 0), BLACK);
+    }
+
+    #[test]
+    fn pencil_paints_single_hard_pixels() {
+        let mut state = test_state(16, 16);
+        state.current_tool = Tool::Pencil;
+        state.brush_size = 5;
+        state.brush_antialiasing = true;
+        state.brush_hardness = 0.2;
+        state.brush_color = Color::BLACK;
+        let from = image_to_screen(&state, Point::new(2.5, 2.5));
+        let to = image_to_screen(&state, Point::new(12.5, 7.5));
+        let positions = stroke_positions(&state, from, to);
+        stamp_positions(&mut state, &positions);
+
+        let image = state.image.read().unwrap().clone();
+        assert!(image.pixels().all(|&pixel| pixel == WHITE || pixel == BLACK));
+        let painted: Vec<(u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel == BLACK)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(painted.len() <= positions.len(), "one pixel per dab");
+        assert!((3..=12).all(|x| painted.iter().any(|&(painted_x, _)| painted_x == x)), "no gaps");
+        assert_eq!(*image.get_pixel(12, 7), BLACK);
+
+        state.current_tool = Tool::Brush;
+        state.brush_shape = BrushShape::Circle;
+        let center = image_to_screen(&state, Point::new(8.5, 12.5));
+        stamp_positions(&mut state, &[center]);
+        let image = state.image.read().unwrap();
+        assert!(image.pixels().any(|&pixel| pixel != WHITE && pixel != BLACK), "the brush does anti-alias");
+    }
+
+    #[test]
+    fn eraser_clears_to_transparency_by_default() {
+        let mut state = test_state(9, 9);
+        state.current_tool = Tool::Eraser;
+        state.brush_shape = BrushShape::Square;
+        state.brush_size = 1;
+        state.background_color = Color::rgb8(0, 0, 255);
+        assert!(state.erase_mode == EraseMode::Transparent);
+
+        begin_stroke(&mut state);
+        let center = image_to_screen(&state, Point::new(4.0, 4.0));
+        stamp_positions(&mut state, &[center]);
+        commit_stroke(&mut state);
+        assert_eq!(state.image.read().unwrap().get_pixel(4, 4)[3], 0);
+        assert_eq!(*state.image.read().unwrap().get_pixel(1, 1), WHITE);
+
+        state.erase_mode = EraseMode::Background;
+        begin_stroke(&mut state);
+        let center = image_to_screen(&state, Point::new(1.0, 1.0));
+        stamp_positions(&mut state, &[center]);
+        commit_stroke(&mut state);
+        assert_eq!(*state.image.read().unwrap().get_pixel(1, 1), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn status_message_keys_exist_in_the_english_resource() {
+        let resource = include_str!("../resources/i18n/en-US/photoshop-mvp.ftl");
+        let defined: std::collections::HashSet<&str> = resource
+            .lines()
+            .filter_map(|line| line.split_once(" = ").map(|(key, _)| key.trim()))
+            .collect();
+        let source = include_str!("main.rs");
+        let used: Vec<&str> = source
+            .match_indices("\"status-")
+            .filter_map(|(start, _)| source[start + 1..].split('"').next())
+            .filter(|key| !key.ends_with('-'))
+            .collect();
+        assert!(used.len() > 50);
+        for key in used {
+            assert!(defined.contains(key), "{} is missing from photoshop-mvp.ftl", key);
+        }
+
+        let message = StatusMessage::new("status-cropped").with_arg("width", 6).with_arg("height", 5u32);
+        assert_eq!(*message.args, [("width", "6".to_string()), ("height", "5".to_string())]);
+        assert_eq!(StatusMessage::default().key, "");
+    }
+
+    #[test]
+    fn stamp_dabs_reuse_the_scaled_stamp_until_its_inputs_change() {
+        let mut state = test_state(32, 32);
+        state.brush_shape = BrushShape::Stamp;
+        state.brush_stamp = Some(Arc::new(RgbaImage::from_pixel(4, 4, RED)));
+        let first = shape_dab(&state, 10, 10).unwrap();
+        let second = shape_dab(&state, 20, 12).unwrap();
+        assert!(Arc::ptr_eq(&first.coverage, &second.coverage));
+        assert!(Arc::ptr_eq(first.colors.as_ref().unwrap(), second.colors.as_ref().unwrap()));
+
+        state.brush_size += 1;
+        let resized = shape_dab(&state, 10, 10).unwrap();
+        assert!(!Arc::ptr_eq(&first.coverage, &resized.coverage));
+        assert_eq!(resized.width, state.brush_size * 2 + 1);
+
+        state.brush_stamp = Some(Arc::new(RgbaImage::from_pixel(4, 4, RED)));
+        let restamped = shape_dab(&state, 10, 10).unwrap();
+        assert!(!Arc::ptr_eq(&resized.coverage, &restamped.coverage));
+    }
+
+    #[test]
+    fn brush_size_input_rejects_zero_and_oversized_values() {
+        let mut state = test_state(8, 8);
+        state.brush_size_input = " 12 ".to_string();
+        apply_brush_size_input(&mut state);
+        assert_eq!(state.brush_size, 12);
+
+        for input in ["0", "abc", "4294967295", &(MAX_BRUSH_SIZE + 1).to_string()] {
+            state.brush_size_input = input.to_string();
+            state.status_message = StatusMessage::default();
+            apply_brush_size_input(&mut state);
+            assert_eq!(state.brush_size, 12, "{input}");
+            assert_eq!(state.status_message.key, "status-brush-size-invalid");
+        }
+
+        state.brush_size_input = MAX_BRUSH_SIZE.to_string();
+        apply_brush_size_input(&mut state);
+        assert_eq!(state.brush_size, MAX_BRUSH_SIZE);
+        assert_eq!(square_mask(MAX_BRUSH_SIZE * 2 + 1).len(), 2001 * 2001);
+    }
+
+    #[test]
+    fn brush_preview_stays_thumbnail_sized_and_keys_on_brush_settings() {
+        let mut state = test_state(8, 8);
+        state.brush_shape = BrushShape::Circle;
+        state.brush_size = MAX_BRUSH_SIZE;
+        let preview = brush_preview_image(&state);
+        assert_eq!(preview.dimensions(), (BRUSH_PREVIEW_RADIUS * 2 + 1, BRUSH_PREVIEW_RADIUS * 2 + 1));
+        assert_eq!(preview.get_pixel(BRUSH_PREVIEW_RADIUS, BRUSH_PREVIEW_RADIUS)[3], 255);
+
+        let key = brush_preview_key(&state);
+        state.brush_size = BRUSH_PREVIEW_RADIUS + 1;
+        state.cursor_pos = Some(Point::new(3.0, 4.0));
+        state.image_generation += 1;
+        assert!(brush_preview_key(&state).same(&key));
+
+        state.brush_size = 4;
+        assert!(!brush_preview_key(&state).same(&key));
+        state.brush_size = MAX_BRUSH_SIZE;
+        state.brush_color = Color::rgb8(0, 0, 255);
+        assert!(!brush_preview_key(&state).same(&key));
+    }
+
+    #[test]
+    fn canvas_cache_is_only_rebuilt_when_the_layers_or_generation_change() {
+        let mut state = test_state(16, 16);
+        let cached = canvas_cache_key(&state);
+
+        state.cursor_pos = Some(Point::new(2.0, 3.0));
+        state.brush_color = Color::rgb8(0, 0, 255);
+        zoom_around(&mut state, 2.0, Point::new(5.0, 5.0));
+        assert!(canvas_cache_is_current(&cached, &canvas_cache_key(&state)));
+
+        mark_image_changed(&mut state);
+        assert!(!canvas_cache_is_current(&cached, &canvas_cache_key(&state)));
+        let cached = canvas_cache_key(&state);
+
+        Arc::make_mut(&mut state.layers);
+        assert!(!canvas_cache_is_current(&cached, &canvas_cache_key(&state)));
+        let cached = canvas_cache_key(&state);
+
+        state.filter_preview = Some(0);
+        assert!(!canvas_cache_is_current(&cached, &canvas_cache_key(&state)));
+    }
+
+    #[test]
+    fn zoom_input_rejects_non_finite_values() {
+        let mut state = test_state(100, 100);
+        state.zoom_input = "250%".to_string();
+        apply_zoom_input(&mut state);
+        assert_eq!(state.zoom, 2.5);
+
+        let pan_offset = state.pan_offset;
+        for input in ["NaN", "inf", "-infinity", "1e400", "abc"] {
+            state.zoom_input = input.to_string();
+            state.status_message = StatusMessage::default();
+            apply_zoom_input(&mut state);
+            assert_eq!(state.zoom, 2.5, "{input}");
+            assert_eq!(state.pan_offset, pan_offset);
+            assert_eq!(state.status_message.key, "status-zoom-invalid");
+        }
+
+        zoom_around(&mut state, f64::NAN, Point::new(10.0, 10.0));
+        assert_eq!(state.zoom, 2.5);
+    }
 }
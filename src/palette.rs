@@ -0,0 +1,88 @@
+use druid::Color;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub fn default_palette() -> Vec<(Color, String)> {
+    [
+        ((255, 0, 0), "Red"),
+        ((0, 255, 0), "Green"),
+        ((0, 0, 255), "Blue"),
+        ((0, 255, 255), "Cyan"),
+        ((139, 69, 19), "Brown"),
+        ((255, 255, 0), "Yellow"),
+        ((196, 55, 140), "test color"),
+        ((72, 61, 139), "test color1"),
+        ((0, 0, 0), "Black"),
+    ]
+    .into_iter()
+    .map(|((r, g, b), name)| (Color::rgb8(r, g, b), name.to_string()))
+    .collect()
+}
+
+pub fn load_gpl(path: &Path) -> io::Result<Vec<(Color, String)>> {
+    Ok(parse_gpl(&fs::read_to_string(path)?))
+}
+
+pub fn save_gpl(path: &Path, name: &str, colors: &[(Color, String)]) -> io::Result<()> {
+    fs::write(path, format_gpl(name, colors))
+}
+
+// Header lines, comments and anything else that does not start with three
+// channel values are skipped rather than rejected.
+pub fn parse_gpl(text: &str) -> Vec<(Color, String)> {
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let r = fields.next()?.parse::<u8>().ok()?;
+            let g = fields.next()?.parse::<u8>().ok()?;
+            let b = fields.next()?.parse::<u8>().ok()?;
+            Some((Color::rgb8(r, g, b), fields.collect::<Vec<_>>().join(" ")))
+        })
+        .collect()
+}
+
+pub fn format_gpl(name: &str, colors: &[(Color, String)]) -> String {
+    let mut text = format!("GIMP Palette\nName: {}\n#\n", name);
+    for (color, color_name) in colors {
+        let (r, g, b, _) = color.as_rgba8();
+        text.push_str(&format!("{:3} {:3} {:3}\t{}\n", r, g, b, color_name));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_and_parse_round_trip() {
+        let colors = default_palette();
+        assert_eq!(parse_gpl(&format_gpl("Defaults", &colors)), colors);
+
+        let path = std::env::temp_dir().join(format!("photoshop-mvp-palette-{}.gpl", std::process::id()));
+        save_gpl(&path, "Defaults", &colors).unwrap();
+        let loaded = load_gpl(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), colors);
+    }
+
+    #[test]
+    fn header_name_columns_and_comments_are_skipped() {
+        let text = "GIMP Palette\nName: Sunset\nColumns: 4\n#\n# a comment\n255 128   0\tOrange Peel\n  0   0   0\n";
+        assert_eq!(
+            parse_gpl(text),
+            [
+                (Color::rgb8(255, 128, 0), "Orange Peel".to_string()),
+                (Color::rgb8(0, 0, 0), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_lines_are_ignored() {
+        let text = "256 0 0 Too bright\n-1 0 0 Negative\n12 34 Missing blue\nred green blue\n\n\t\n10 20 30 Kept\n";
+        assert_eq!(parse_gpl(text), [(Color::rgb8(10, 20, 30), "Kept".to_string())]);
+    }
+}
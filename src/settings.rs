@@ -0,0 +1,75 @@
+use druid::Color;
+use std::fs;
+use std::io;
+
+const SETTINGS_PATH: &str = "settings.cfg";
+
+pub struct Settings {
+    pub default_brush_color: Color,
+    pub custom_palette: Vec<Color>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            default_brush_color: Color::BLACK,
+            custom_palette: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Settings {
+        let mut settings = Settings::default();
+        let Ok(contents) = fs::read_to_string(SETTINGS_PATH) else {
+            return settings;
+        };
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            match key.trim() {
+                "default_brush_color" => {
+                    if let Some(color) = parse_rgb(value) {
+                        settings.default_brush_color = color;
+                    }
+                }
+                "custom_palette" => {
+                    settings.custom_palette = value.split(';').filter_map(parse_rgb).collect();
+                }
+                _ => {}
+            }
+        }
+        settings
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let palette = self
+            .custom_palette
+            .iter()
+            .map(|color| format_rgb(*color))
+            .collect::<Vec<_>>()
+            .join(";");
+        fs::write(
+            SETTINGS_PATH,
+            format!(
+                "default_brush_color={}\ncustom_palette={}\n",
+                format_rgb(self.default_brush_color),
+                palette
+            ),
+        )
+    }
+}
+
+pub fn parse_rgb(value: &str) -> Option<Color> {
+    let mut channels = value.split(',').map(|channel| channel.trim().parse::<u8>());
+    let r = channels.next()?.ok()?;
+    let g = channels.next()?.ok()?;
+    let b = channels.next()?.ok()?;
+    Some(Color::rgb8(r, g, b))
+}
+
+fn format_rgb(color: Color) -> String {
+    let (r, g, b, _) = color.as_rgba8();
+    format!("{},{},{}", r, g, b)
+}